@@ -0,0 +1,34 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use super::{Barks, GameLog, Map, Name, Position, RunState};
+
+// The percentage chance of each creature the player can see saying something on a monster turn.
+const BARK_CHANCE: i32 = 5;
+
+pub struct BarkSystem {}
+
+impl<'a> System<'a> for BarkSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        ReadStorage<'a, Barks>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, run_state, mut game_log, mut random_number_generator, barks_components, names, positions) = data;
+        if *run_state != RunState::MonsterTurn { return; }
+
+        for (barks, name, position) in (&barks_components, &names, &positions).join() {
+            // Only what the player can see is close enough to hear.
+            if barks.lines.is_empty() || !map.visible_tiles[map.xy_idx(position.x, position.y)] { continue; }
+            if random_number_generator.range(0, 100) >= BARK_CHANCE { continue; }
+            let line = &barks.lines[random_number_generator.range(0, barks.lines.len() as i32) as usize];
+            game_log.entries.push(format!("{} says: \"{}\"", name.name, line));
+        }
+    }
+}
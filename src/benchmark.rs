@@ -0,0 +1,262 @@
+use std::time::{Duration, Instant};
+
+use rltk::{Point, RandomNumberGenerator};
+use specs::prelude::*;
+use super::{
+    Ability,
+    AreaOfEffect,
+    BlocksTile,
+    Charges,
+    CombatStats,
+    Companion,
+    Confusion,
+    Faction,
+    FactionTable,
+    InBackpack,
+    InflictsDamage,
+    Item,
+    KeepsDistance,
+    Map,
+    MapIndexingSystem,
+    Monster,
+    MonsterAI,
+    PathingMapSystem,
+    PathingMaps,
+    Position,
+    ProvidesHealing,
+    Ranged,
+    Reaction,
+    RunState,
+    TileType,
+    UsesItems,
+    Viewshed,
+    Wanders,
+    WantsToMelee,
+    WantsToPickupItem,
+    WantsToUseItem,
+};
+
+const ARENA_WIDTH: i32 = 250;
+const ARENA_HEIGHT: i32 = 250;
+const MONSTER_COUNTS: [usize; 3] = [10, 100, 1000];
+const TURNS: u32 = 10;
+
+/// Times a monster turn with 10, 100 and 1000 monsters chasing the player across a large map,
+/// comparing the shared flow maps against the old approach of one A* search per monster.
+/// Both time the whole turn: the monsters' AI, including pathing and moving, then indexing the map again.
+/// Run with "cargo run --release -- --benchmark".
+pub fn run_monster_turn_benchmark() {
+    println!("Monster turn benchmark on a {}x{} map ({} turns per run)", ARENA_WIDTH, ARENA_HEIGHT, TURNS);
+    println!("{:>10} | {:>18} | {:>18}", "Monsters", "Flow maps (ms)", "A* per monster (ms)");
+    for monster_count in MONSTER_COUNTS.iter() {
+        let flow_map_time = time_monster_turns(*monster_count, run_flow_map_turn);
+        let a_star_time = time_monster_turns(*monster_count, run_a_star_turn);
+        println!(
+            "{:>10} | {:>18.3} | {:>18.3}",
+            monster_count,
+            milliseconds(flow_map_time),
+            milliseconds(a_star_time),
+        );
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// Average time for one monster turn, as "run_turn" runs it.
+fn time_monster_turns(monster_count: usize, run_turn: fn(&World)) -> Duration {
+    let mut ecs = build_world(monster_count);
+    let mut total = Duration::from_secs(0);
+    for _turn in 0..TURNS {
+        let start = Instant::now();
+        run_turn(&ecs);
+        total += start.elapsed();
+
+        // Melee is resolved elsewhere; just discard it so monsters keep moving.
+        ecs.write_storage::<WantsToMelee>().clear();
+        ecs.maintain();
+    }
+    total / TURNS
+}
+
+// A monster turn using the systems the game runs.
+fn run_flow_map_turn(ecs: &World) {
+    let mut pathing_map_system = PathingMapSystem {};
+    pathing_map_system.run_now(ecs);
+    let mut monster_ai_system = MonsterAI {};
+    monster_ai_system.run_now(ecs);
+    let mut map_indexing_system = MapIndexingSystem {};
+    map_indexing_system.run_now(ecs);
+}
+
+// A monster turn as the game ran it before the flow maps.
+fn run_a_star_turn(ecs: &World) {
+    let mut a_star_monster_ai = AStarMonsterAI {};
+    a_star_monster_ai.run_now(ecs);
+    let mut map_indexing_system = MapIndexingSystem {};
+    map_indexing_system.run_now(ecs);
+}
+
+// The MonsterAI from before the flow maps, kept to compare against: every monster that sees the player
+// runs its own A* search to them.
+struct AStarMonsterAI {}
+
+impl<'a> System<'a> for AStarMonsterAI {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, Point>,
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, Monster>,
+        WriteStorage<'a, Confusion>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Viewshed>,
+        WriteStorage<'a, WantsToMelee>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player_entity,
+            player_position,
+            mut map,
+            monsters,
+            mut confusion,
+            mut positions,
+            mut viewsheds,
+            mut wants_to_melee,
+        ) = data;
+
+        for (entity, _monster, monster_position, monster_viewshed)
+        in (&entities, &monsters, &mut positions, &mut viewsheds).join() {
+            if let Some(is_confused) = confusion.get_mut(entity) {
+                is_confused.turns -= 1;
+                if is_confused.turns < 1 {
+                    confusion.remove(entity);
+                }
+                continue;
+            }
+
+            let distance_to_player = rltk::DistanceAlg::Pythagoras.distance2d(
+                Point::new(monster_position.x, monster_position.y),
+                *player_position,
+            );
+            if distance_to_player < 1.5 {
+                wants_to_melee.insert(entity, WantsToMelee { target: *player_entity }).expect("Unable to insert WantsToMelee component.");
+            } else if monster_viewshed.visible_tiles.contains(&*player_position) {
+                let path_to_player = rltk::a_star_search(
+                    map.xy_idx(monster_position.x, monster_position.y),
+                    map.xy_idx(player_position.x, player_position.y),
+                    &*map);
+                if path_to_player.success && path_to_player.steps.len() > 1 {
+                    let index = map.xy_idx(monster_position.x, monster_position.y);
+                    map.blocked_tiles[index] = false;
+                    monster_position.x = path_to_player.steps[1] as i32 % map.width;
+                    monster_position.y = path_to_player.steps[1] as i32 / map.width;
+                    map.blocked_tiles[path_to_player.steps[1]] = true;
+                    monster_viewshed.dirty = true;
+                }
+            }
+        }
+    }
+}
+
+// Builds a headless world with an open arena, the player in the middle and monsters scattered around it.
+fn build_world(monster_count: usize) -> World {
+    let mut ecs = World::new();
+    ecs.register::<Ability>();
+    ecs.register::<AreaOfEffect>();
+    ecs.register::<BlocksTile>();
+    ecs.register::<Charges>();
+    ecs.register::<CombatStats>();
+    ecs.register::<Companion>();
+    ecs.register::<Confusion>();
+    ecs.register::<Faction>();
+    ecs.register::<InBackpack>();
+    ecs.register::<InflictsDamage>();
+    ecs.register::<Item>();
+    ecs.register::<KeepsDistance>();
+    ecs.register::<Monster>();
+    ecs.register::<Position>();
+    ecs.register::<ProvidesHealing>();
+    ecs.register::<Ranged>();
+    ecs.register::<UsesItems>();
+    ecs.register::<Viewshed>();
+    ecs.register::<Wanders>();
+    ecs.register::<WantsToMelee>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<WantsToUseItem>();
+
+    // Seeded so that every run paths over the same arena.
+    let mut rng = RandomNumberGenerator::seeded(monster_count as u64);
+    let map = build_arena(&mut rng);
+    let player_position = Point::new(ARENA_WIDTH / 2, ARENA_HEIGHT / 2);
+
+    let player_entity = ecs
+        .create_entity()
+        .with(CombatStats { max_hp: 30, hp: 30, defense: 2, power: 5 })
+        .with(Faction { name: "Player".to_string() })
+        .with(Position { x: player_position.x, y: player_position.y })
+        .build();
+
+    let mut spawned = 0;
+    while spawned < monster_count {
+        let x = rng.range(1, ARENA_WIDTH - 1);
+        let y = rng.range(1, ARENA_HEIGHT - 1);
+        let index = map.xy_idx(x, y);
+        if map.tiles[index] != TileType::Floor || (x == player_position.x && y == player_position.y) { continue; }
+        ecs
+            .create_entity()
+            .with(BlocksTile {})
+            .with(CombatStats { max_hp: 16, hp: 16, defense: 1, power: 4 })
+            .with(Faction { name: "Orcs".to_string() })
+            .with(Monster {})
+            .with(Position { x, y })
+            // Every monster can see the player, so every monster paths each turn.
+            .with(Viewshed { visible_tiles: vec![player_position], range: ARENA_WIDTH, dirty: false })
+            .build();
+        spawned += 1;
+    }
+
+    ecs.insert(PathingMaps::new(map.width, map.height));
+    ecs.insert(FactionTable::new().add_default("Orcs", Reaction::Attack));
+    ecs.insert(rng);
+    ecs.insert(map);
+    ecs.insert(player_entity);
+    ecs.insert(player_position);
+    ecs.insert(RunState::MonsterTurn);
+
+    let mut map_indexing_system = MapIndexingSystem {};
+    map_indexing_system.run_now(&ecs);
+    ecs
+}
+
+// A walled arena with scattered pillars, so paths aren't straight lines.
+fn build_arena(rng: &mut RandomNumberGenerator) -> Map {
+    let tile_count = (ARENA_WIDTH * ARENA_HEIGHT) as usize;
+    let mut map = Map {
+        tiles: vec![TileType::Floor; tile_count],
+        rooms: Vec::new(),
+        width: ARENA_WIDTH,
+        height: ARENA_HEIGHT,
+        depth: 1,
+        revealed_tiles: vec![false; tile_count],
+        visible_tiles: vec![false; tile_count],
+        blocked_tiles: vec![false; tile_count],
+        tile_contents: vec![Vec::new(); tile_count],
+    };
+    for y in 0..ARENA_HEIGHT {
+        for x in 0..ARENA_WIDTH {
+            let is_edge = x == 0 || y == 0 || x == ARENA_WIDTH - 1 || y == ARENA_HEIGHT - 1;
+            if is_edge || rng.roll_dice(1, 10) == 1 {
+                let index = map.xy_idx(x, y);
+                map.tiles[index] = TileType::Wall;
+            }
+        }
+    }
+    let center_index = map.xy_idx(ARENA_WIDTH / 2, ARENA_HEIGHT / 2);
+    map.tiles[center_index] = TileType::Floor;
+    map
+}
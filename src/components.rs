@@ -6,14 +6,41 @@ use specs::saveload::{Marker, ConvertSaveload};
 use specs::error::NoError;
 
 // === Components ===
+// An ability (ranged attack, spell) that a creature can use like an item.
+// The ability entity carries the same effect components as items do (InflictsDamage, Confusion, ...).
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Ability {
+    pub owner: Entity,
+    pub cooldown: i32,
+    pub turns_until_ready: i32,
+}
+
 #[derive(Component, Debug, ConvertSaveload, Clone, )]
 pub struct AreaOfEffect {
     pub radius: i32,
 }
 
+// Things a creature says now and then, when the player is in sight.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Barks {
+    pub lines: Vec<String>,
+}
+
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct BlocksTile {}
 
+// An item that can be used "max" times before it needs recharging, like a wand.
+// Using it takes a charge instead of using it up.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Charges {
+    pub current: i32,
+    pub max: i32,
+}
+
+// An item that turns its target into one of the player's companions.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Charms {}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct CombatStats {
     pub max_hp: i32,
@@ -22,6 +49,19 @@ pub struct CombatStats {
     pub power: i32,
 }
 
+// A creature that follows the player around and takes orders.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Companion {
+    pub order: CompanionOrder,
+}
+
+#[derive(Debug, ConvertSaveload, Clone, PartialEq)]
+pub enum CompanionOrder {
+    Follow,
+    Stay,
+    Attack { target: Entity },
+}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct Confusion {
     pub turns: i32,
@@ -32,11 +72,81 @@ pub struct Confusion {
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Consumable {}
 
+// This component is a 'flag' component.
+// Chests, barrels and corpses hold items, which are InContainer with it as their owner.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Container {}
+
+// A cursed item turns on its user, and can't be dropped once picked up.
+// The player only sees the curse once it has been "discovered".
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Cursed {
+    pub discovered: bool,
+}
+
+// On an item, lets the user see every item on the level for this many turns.
+// On the player, the turns they have left.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct DetectItems {
+    pub turns: i32,
+}
+
+// On an item, lets the user see every monster on the level for this many turns.
+// On the player, the turns they have left.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct DetectMonsters {
+    pub turns: i32,
+}
+
+// Carrying more than they comfortably can slows the player down: every other turn, the monsters get to act twice.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Encumbered {
+    // Monster turns taken since the player became encumbered; every third one is the extra turn.
+    pub monster_turns: i32,
+}
+
+// The side a creature is on. See "FactionTable" for how factions react to each other.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Faction {
+    pub name: String,
+}
+
+// A pile of gold. Picking it up adds it to the collector's Purse, if they have one.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Gold {
+    pub amount: i32,
+}
+
+// How hungry the player is, and how many more of their turns until they get hungrier.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct HungerClock {
+    pub state: HungerState,
+    pub duration: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum HungerState {
+    WellFed,
+    Normal,
+    Hungry,
+    Starving,
+}
+
+// Reading it identifies everything the reader is carrying.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Identifies {}
+
 #[derive(Component, Debug, ConvertSaveload)]
 pub struct InBackpack {
     pub owner: Entity,
 }
 
+// An item lying inside a Container, like InBackpack is for items being carried.
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct InContainer {
+    pub owner: Entity,
+}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct InflictsDamage {
     pub damage: i32,
@@ -45,6 +155,50 @@ pub struct InflictsDamage {
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Item {}
 
+// A spell 'owner' has learned. Like an Ability, it carries the effect components it applies,
+// and casting it costs 'mana_cost' mana instead of using it up.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct KnownSpell {
+    pub owner: Entity,
+    pub mana_cost: i32,
+}
+
+// Monsters with this component try to stay this many tiles away from their target.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct KeepsDistance {
+    pub distance: i32,
+}
+
+// This component is a 'flag' component.
+// Creatures with it leave a corpse behind when they die.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct LeavesCorpse {}
+
+// A container that won't open until the player uses up a 'key', by item name.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Locked {
+    pub key: String,
+}
+
+// The name of the loot table (defined in the raws) rolled when this creature dies.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct LootTable {
+    pub table: String,
+}
+
+// What a creature spends to cast spells. It regains a point every few turns.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Mana {
+    pub current: i32,
+    pub max: i32,
+    // Turns since the last point was regained.
+    pub regeneration_turns: i32,
+}
+
+// An item that reveals the whole level's map.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct MagicMapper {}
+
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Monster {}
 
@@ -53,9 +207,18 @@ pub struct Name {
     pub name: String,
 }
 
+// This component is a 'flag' component.
+// A creature the player swaps places with instead of attacking, like the townsfolk.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Peaceful {}
+
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Player {}
 
+// Eating it leaves the eater well fed.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesFood {}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct ProvidesHealing {
     pub heal_amount: i32,
@@ -67,11 +230,32 @@ pub struct Position {
     pub y: i32,
 }
 
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Purse {
+    pub gold: i32,
+}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct Ranged {
     pub range: i32,
 }
 
+// A bow or the like: it shoots the item named 'ammo' at targets up to 'range' tiles away.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct RangedWeapon {
+    pub ammo: String,
+    pub damage: i32,
+    pub range: i32,
+}
+
+// Reading it restores every charge of everything the reader is carrying.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Recharges {}
+
+// Reading it lifts the curse from everything the reader is carrying.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct RemovesCurse {}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct Renderer {
     pub glyph: rltk::FontCharType,
@@ -84,9 +268,21 @@ pub struct Renderer {
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct SerializationHelper {
     pub map: super::map::Map,
+    #[serde(default)]
+    pub item_identification: super::ItemIdentification,
 }
 
 
+// An item that breaks when thrown, spilling its effects over the tiles around where it lands.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Shatters {}
+
+// Identical items carried or lying together are one entity with a quantity, e.g. "Health Potion x3".
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Stackable {
+    pub quantity: i32,
+}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct SufferDamage {
     pub amount: Vec<i32>,
@@ -104,6 +300,38 @@ impl SufferDamage {
     }
 }
 
+// An item that summons a companion next to the user, by spawner name.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Summons {
+    pub name: String,
+}
+
+// Reading it teaches the reader the spell with this name, rather than having an effect itself.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct TeachesSpell {
+    pub name: String,
+}
+
+// An item that moves the user to a random free floor tile.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Teleports {}
+
+// This component is a 'flag' component.
+// Creatures with it pick up items they walk over, and use them the way the player would.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct UsesItems {}
+
+// What a shop charges for an item, in gold. For a stack, this is the value of one of them.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Value {
+    pub gold: i32,
+}
+
+// This component is a 'flag' component.
+// A shopkeeper, who trades the items in their backpack with the player instead of fighting.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Vendor {}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct Viewshed {
     pub visible_tiles: Vec<rltk::Point>,
@@ -111,6 +339,17 @@ pub struct Viewshed {
     pub dirty: bool,
 }
 
+// How heavy an item is, in pounds. For a stack, this is the weight of one of them.
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct Weight {
+    pub pounds: f32,
+}
+
+// This component is a 'flag' component.
+// Creatures with it walk around at random when they have nothing better to do.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Wanders {}
+
 #[derive(Component, Debug, ConvertSaveload)]
 pub struct WantsToDrinkPotion {
     pub potion: Entity,
@@ -121,6 +360,13 @@ pub struct WantsToDropItem {
     pub item: Entity,
 }
 
+// This component indicates intent.
+// The entity wants to learn the spell called 'name'.
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToLearnSpell {
+    pub name: String,
+}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct WantsToMelee {
     pub target: Entity,
@@ -134,6 +380,31 @@ pub struct WantsToPickupItem {
     pub item: Entity,
 }
 
+// This component indicates intent.
+// The entity wants to shoot one 'ammo' at 'target' with 'weapon'.
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToShoot {
+    pub weapon: Entity,
+    pub ammo: Entity,
+    pub target: Entity,
+}
+
+// This component indicates intent.
+// The entity wants 'name' summoned as a companion somewhere around 'position'.
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToSummon {
+    pub name: String,
+    pub position: rltk::Point,
+}
+
+// This component indicates intent.
+// The entity wants to throw 'item' at the 'target' tile.
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToThrowItem {
+    pub item: Entity,
+    pub target: rltk::Point,
+}
+
 #[derive(Component, Debug, ConvertSaveload)]
 pub struct WantsToUseItem {
     pub item: Entity,
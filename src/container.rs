@@ -0,0 +1,122 @@
+use rltk::Point;
+use specs::prelude::*;
+use super::inventory_system::{can_carry, stack_name, stack_weight};
+use super::{
+    spawner,
+    Container,
+    Cursed,
+    GameLog,
+    Gold,
+    InBackpack,
+    InContainer,
+    ItemIdentification,
+    Locked,
+    Name,
+    Position,
+    Purse,
+    Stackable,
+    Weight,
+};
+
+/// The container the player can reach: one on their own tile if there is one, otherwise one next to them.
+pub fn reachable_container(ecs: &World) -> Option<Entity> {
+    let entities = ecs.entities();
+    let player_position = ecs.fetch::<Point>();
+    let containers = ecs.read_storage::<Container>();
+    let positions = ecs.read_storage::<Position>();
+    (&entities, &containers, &positions)
+        .join()
+        .map(|(entity, _container, position)| {
+            (entity, i32::max((position.x - player_position.x).abs(), (position.y - player_position.y).abs()))
+        })
+        .filter(|(_entity, distance)| *distance <= 1)
+        .min_by_key(|(_entity, distance)| *distance)
+        .map(|(entity, _distance)| entity)
+}
+
+/// Unlocks "container" with a key from the player's backpack, using the key up.
+/// Returns whether it's open now; containers that were never locked always are.
+pub fn try_unlock(ecs: &mut World, container: Entity) -> bool {
+    let player_entity = *ecs.fetch::<Entity>();
+    let key_name = match ecs.read_storage::<Locked>().get(container) {
+        None => return true,
+        Some(locked) => locked.key.clone(),
+    };
+    let container_name = ecs.read_storage::<Name>().get(container).unwrap().name.clone();
+    let key = {
+        let entities = ecs.entities();
+        let in_backpacks = ecs.read_storage::<InBackpack>();
+        let names = ecs.read_storage::<Name>();
+        (&entities, &in_backpacks, &names)
+            .join()
+            .find(|(_item, in_backpack, name)| in_backpack.owner == player_entity && name.name == key_name)
+            .map(|(item, _in_backpack, _name)| item)
+    };
+
+    match key {
+        None => {
+            ecs.write_resource::<GameLog>().entries.push(format!(
+                "The {} is locked, and you have no {} to open it.", container_name, key_name
+            ));
+            false
+        }
+        Some(key) => {
+            let used_key = spawner::split_stack(ecs, key);
+            ecs.delete_entity(used_key).expect("Unable to delete Entity");
+            ecs.write_storage::<Locked>().remove(container);
+            ecs.write_resource::<GameLog>().entries.push(format!(
+                "You unlock the {} with your {}.", container_name, key_name
+            ));
+            true
+        }
+    }
+}
+
+/// The player takes "item" out of "container". Gold goes straight into their purse.
+pub fn take_item(ecs: &mut World, container: Entity, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let container_name = ecs.read_storage::<Name>().get(container).unwrap().name.clone();
+
+    let gold = ecs.read_storage::<Gold>().get(item).map(|gold| gold.amount);
+    if let Some(amount) = gold {
+        if let Some(purse) = ecs.write_storage::<Purse>().get_mut(player_entity) {
+            purse.gold += amount;
+        }
+        ecs.delete_entity(item).expect("Unable to delete Entity");
+        ecs.write_resource::<GameLog>().entries.push(format!("You take {} gold from the {}.", amount, container_name));
+        return;
+    }
+
+    let item_name = item_name(ecs, item);
+    // The player can only carry so much, just like when picking things up.
+    let pounds = ecs.read_storage::<Weight>().get(item).map_or(0.0, |weight| stack_weight(weight, ecs.read_storage::<Stackable>().get(item)));
+    if !can_carry(ecs, player_entity, pounds) {
+        ecs.write_resource::<GameLog>().entries.push(format!("You can't carry the {} as well. Your pack is too heavy.", item_name));
+        return;
+    }
+    ecs.write_storage::<InContainer>().remove(item);
+    spawner::put_in_backpack(ecs, item, player_entity);
+    ecs.write_resource::<GameLog>().entries.push(format!("You take the {} from the {}.", item_name, container_name));
+}
+
+/// The player puts "item" from their backpack into "container". Cursed items won't leave their hands, just like when dropping them.
+pub fn put_item(ecs: &mut World, container: Entity, item: Entity) {
+    let container_name = ecs.read_storage::<Name>().get(container).unwrap().name.clone();
+    let item_name = item_name(ecs, item);
+    if let Some(cursed) = ecs.write_storage::<Cursed>().get_mut(item) {
+        cursed.discovered = true;
+        ecs.write_resource::<GameLog>().entries.push(format!("You can't let go of the {}. It's cursed!", item_name));
+        return;
+    }
+
+    ecs.write_storage::<InBackpack>().remove(item);
+    spawner::put_in_container(ecs, item, container);
+    ecs.write_resource::<GameLog>().entries.push(format!("You put the {} in the {}.", item_name, container_name));
+}
+
+// The item's name as the player knows it, with how many there are.
+fn item_name(ecs: &World, item: Entity) -> String {
+    let names = ecs.read_storage::<Name>();
+    let display_name = ecs.fetch::<ItemIdentification>().display_name(&names.get(item).unwrap().name);
+    stack_name(display_name, ecs.read_storage::<Stackable>().get(item))
+}
@@ -1,7 +1,7 @@
 use specs::prelude::*;
 use crate::game_log::GameLog;
 use crate::Player;
-use super::{CombatStats, SufferDamage, Name};
+use super::{spawner, Ability, CombatStats, InBackpack, Item, LeavesCorpse, LootTable, Name, Position, SufferDamage};
 
 pub struct DamageSystem {}
 
@@ -13,7 +13,7 @@ impl<'a> System<'a> for DamageSystem {
 
     fn run(&mut self, data: Self::SystemData) {
         let (mut combat_stats, mut suffer_damages) = data;
-        for (mut combat_stat, suffer_damage) in (&mut combat_stats, &suffer_damages).join() {
+        for (combat_stat, suffer_damage) in (&mut combat_stats, &suffer_damages).join() {
             combat_stat.hp -= suffer_damage.amount.iter().sum::<i32>();
         }
         // Clear the ECS Storage of SufferDamage of all SufferDamage components to prepare for the next tick.
@@ -51,7 +51,81 @@ pub fn delete_the_dead(ecs: &mut World) {
         }
     }
 
+    // Abilities belong to their owner, so they die with them.
+    {
+        let entities = ecs.entities();
+        let abilities = ecs.read_storage::<Ability>();
+        for (entity, ability) in (&entities, &abilities).join() {
+            if the_dead.contains(&ability.owner) {
+                the_dead.push(entity);
+            }
+        }
+    }
+
+    // Note where corpses and loot should go before the dead are deleted.
+    let mut remains: Vec<Remains> = Vec::new();
+    {
+        let entities = ecs.entities();
+        let in_backpacks = ecs.read_storage::<InBackpack>();
+        let leaves_corpse_components = ecs.read_storage::<LeavesCorpse>();
+        let loot_tables = ecs.read_storage::<LootTable>();
+        let names = ecs.read_storage::<Name>();
+        let positions = ecs.read_storage::<Position>();
+        for victim in the_dead.iter() {
+            if let Some(position) = positions.get(*victim) {
+                let corpse = match (leaves_corpse_components.get(*victim), names.get(*victim)) {
+                    (Some(_), Some(name)) => Some(name.name.clone()),
+                    _ => None,
+                };
+                let loot_table = loot_tables.get(*victim).map(|loot_table| loot_table.table.clone());
+                let belongings = (&entities, &in_backpacks)
+                    .join()
+                    .filter(|(_item, in_backpack)| in_backpack.owner == *victim)
+                    .map(|(item, _in_backpack)| item)
+                    .collect();
+                remains.push(Remains { position: position.clone(), corpse, loot_table, belongings });
+            }
+        }
+    }
+
+    // Whatever the dead were carrying falls to the floor where they died, unless there's a corpse to hold it.
+    {
+        let mut in_backpacks = ecs.write_storage::<InBackpack>();
+        let mut positions = ecs.write_storage::<Position>();
+        for victim_remains in remains.iter() {
+            for item in victim_remains.belongings.iter() {
+                in_backpacks.remove(*item);
+                if victim_remains.corpse.is_none() {
+                    positions.insert(*item, victim_remains.position.clone()).expect("Unable to insert Position component.");
+                }
+            }
+        }
+    }
+
     for victim in the_dead {
         ecs.delete_entity(victim).expect("Unable to delete Entity"); // Line Y: Mutating ecs.
     }
-}
\ No newline at end of file
+
+    for victim_remains in remains {
+        let Position { x, y } = victim_remains.position;
+        let corpse = victim_remains.corpse.map(|name| spawner::spawn_corpse(ecs, &name, x, y));
+        let loot = victim_remains.loot_table.and_then(|table| spawner::drop_loot(ecs, &table, x, y));
+        if let Some(corpse) = corpse {
+            for item in victim_remains.belongings.into_iter().chain(loot) {
+                if ecs.read_storage::<Item>().contains(item) {
+                    ecs.write_storage::<Position>().remove(item);
+                    spawner::put_in_container(ecs, item, corpse);
+                }
+            }
+        }
+    }
+}
+
+// What a dead creature leaves behind, noted before it's deleted.
+struct Remains {
+    position: Position,
+    // The name of the creature, if it leaves a corpse.
+    corpse: Option<String>,
+    loot_table: Option<String>,
+    belongings: Vec<Entity>,
+}
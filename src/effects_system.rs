@@ -0,0 +1,408 @@
+use std::collections::VecDeque;
+
+use rltk::{Point, RandomNumberGenerator};
+use specs::prelude::*;
+use crate::hunger_system::WELL_FED_TURNS;
+use crate::inventory_system::identify_item;
+use super::{
+    spawner,
+    Charges,
+    CombatStats,
+    Companion,
+    Confusion,
+    Cursed,
+    DetectItems,
+    DetectMonsters,
+    Faction,
+    GameLog,
+    HungerClock,
+    HungerState,
+    InBackpack,
+    ItemIdentification,
+    Map,
+    Monster,
+    Name,
+    Position,
+    RunState,
+    SufferDamage,
+    Viewshed,
+    Wanders,
+    WantsToLearnSpell,
+    WantsToSummon,
+};
+
+/// What an effect does to each creature it reaches.
+#[derive(Debug, Clone)]
+pub enum EffectType {
+    /// Turns the target into one of the player's companions.
+    Charm,
+    Confusion { turns: i32 },
+    Damage { amount: i32 },
+    DetectItems { turns: i32 },
+    DetectMonsters { turns: i32 },
+    /// Empties every charged item the target carries.
+    DrainCharges,
+    /// Makes the player forget the level, apart from what they can see.
+    ForgetMap,
+    Healing { amount: i32 },
+    /// Tells the player what everything they carry is, curses included.
+    Identify,
+    LearnSpell { name: String },
+    MagicMapping,
+    /// Fills every charged item the target carries.
+    Recharge,
+    RemoveCurse,
+    Summon { name: String },
+    Teleport,
+    WellFed,
+}
+
+/// Where an effect lands. Only creatures (anything with CombatStats) there are affected.
+#[derive(Debug, Clone)]
+pub enum Targets {
+    /// Whoever caused the effect.
+    User,
+    Entity { target: Entity },
+    Tile { tile: Point },
+    Tiles { tiles: Vec<Point> },
+    /// Every tile within "radius" of "center" that can be seen from it, like a fireball's blast.
+    Area { center: Point, radius: i32 },
+}
+
+/// One effect waiting to be applied.
+#[derive(Debug, Clone)]
+pub struct Effect {
+    /// Who caused it, if anyone.
+    pub creator: Option<Entity>,
+    /// What caused it (e.g. "Fireball Scroll"), for the log.
+    pub source: String,
+    pub effect_type: EffectType,
+    pub targets: Targets,
+}
+
+/// Effects from every source (items, spells, abilities, thrown potions...), applied in order by the EffectsSystem.
+pub struct EffectQueue {
+    effects: VecDeque<Effect>,
+}
+
+impl EffectQueue {
+    // Constructor
+    pub fn new() -> EffectQueue {
+        EffectQueue { effects: VecDeque::new() }
+    }
+
+    pub fn add(&mut self, creator: Option<Entity>, source: &str, effect_type: EffectType, targets: Targets) {
+        self.effects.push_back(Effect {
+            creator,
+            source: source.to_string(),
+            effect_type,
+            targets,
+        });
+    }
+}
+
+impl Default for EffectQueue {
+    fn default() -> Self {
+        EffectQueue::new()
+    }
+}
+
+pub struct EffectsSystem {}
+
+impl<'a> System<'a> for EffectsSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, Monster>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, Companion>,
+        WriteStorage<'a, Confusion>,
+        WriteStorage<'a, Faction>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, Wanders>,
+        // Effects on what the target carries or knows.
+        (
+            ReadStorage<'a, InBackpack>,
+            WriteExpect<'a, ItemIdentification>,
+            WriteStorage<'a, Charges>,
+            WriteStorage<'a, Cursed>,
+            WriteStorage<'a, HungerClock>,
+            WriteStorage<'a, WantsToLearnSpell>,
+            WriteStorage<'a, WantsToSummon>,
+        ),
+        // Effects on the level as a whole, or on where the target is in it.
+        (
+            WriteExpect<'a, Point>,
+            WriteExpect<'a, RandomNumberGenerator>,
+            WriteExpect<'a, RunState>,
+            WriteStorage<'a, DetectItems>,
+            WriteStorage<'a, DetectMonsters>,
+            WriteStorage<'a, Viewshed>,
+        ),
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player_entity,
+            mut effect_queue,
+            mut game_log,
+            mut map,
+            monsters,
+            names,
+            mut combat_stats_components,
+            mut companions,
+            mut confusion_components,
+            mut factions,
+            mut positions,
+            mut suffer_damage_components,
+            mut wanders_components,
+            (
+                in_backpacks,
+                mut item_identification,
+                mut charges_components,
+                mut cursed_components,
+                mut hunger_clocks,
+                mut wants_to_learn_spells,
+                mut wants_to_summon_components,
+            ),
+            (
+                mut player_position,
+                mut rng,
+                mut run_state,
+                mut detect_items_components,
+                mut detect_monsters_components,
+                mut viewsheds,
+            ),
+        ) = data;
+
+        while let Some(effect) = effect_queue.effects.pop_front() {
+            for target in target_entities(&map, &effect.targets, effect.creator) {
+                // Items and props can be on the same tiles, but only creatures are affected.
+                let combat_stats = match combat_stats_components.get_mut(target) {
+                    None => continue,
+                    Some(combat_stats) => combat_stats,
+                };
+
+                // Only log what the player can see.
+                let is_player = target == *player_entity;
+                let is_target_visible = is_player
+                    || effect.creator == Some(*player_entity)
+                    || positions.get(target).is_some_and(|position| map.visible_tiles[map.xy_idx(position.x, position.y)]);
+                let target_name = if is_player { "you" } else { &names.get(target).unwrap().name };
+
+                match &effect.effect_type {
+                    EffectType::Charm => {
+                        // Only monsters can be charmed, and the player's allies already are.
+                        if !monsters.contains(target) || companions.contains(target) { continue; }
+                        spawner::recruit_companion(&mut factions, &mut companions, &mut wanders_components, target);
+                        if is_target_visible {
+                            game_log.entries.push(format!("The {} charms {}, who is now your ally!", effect.source, target_name));
+                        }
+                    }
+                    EffectType::Confusion { turns } => {
+                        confusion_components.insert(target, Confusion { turns: *turns })
+                            .expect("Unable to insert Confusion component.");
+                        if is_target_visible {
+                            game_log.entries.push(format!("The {} confuses {}!", effect.source, target_name));
+                        }
+                    }
+                    EffectType::Damage { amount } => {
+                        // Armor can soak up the whole of a blow.
+                        if *amount <= 0 {
+                            if is_target_visible {
+                                game_log.entries.push(format!("The {} is unable to hurt {}.", effect.source, target_name));
+                            }
+                            continue;
+                        }
+                        SufferDamage::new_damage(&mut suffer_damage_components, target, *amount);
+                        if is_target_visible {
+                            game_log.entries.push(format!("The {} hits {} for {} damage.", effect.source, target_name, amount));
+                        }
+                    }
+                    // Only the player's view is drawn, so only they can detect anything.
+                    EffectType::DetectItems { turns } => {
+                        if !is_player { continue; }
+                        detect_items_components.insert(target, DetectItems { turns: *turns })
+                            .expect("Unable to insert DetectItems component.");
+                        game_log.entries.push("You sense the items lying around the level.".to_string());
+                    }
+                    EffectType::DetectMonsters { turns } => {
+                        if !is_player { continue; }
+                        detect_monsters_components.insert(target, DetectMonsters { turns: *turns })
+                            .expect("Unable to insert DetectMonsters component.");
+                        game_log.entries.push("You sense the presence of monsters.".to_string());
+                    }
+                    EffectType::DrainCharges => {
+                        for carried_item in carried_items(&entities, &in_backpacks, target) {
+                            if let Some(charges) = charges_components.get_mut(carried_item) {
+                                charges.current = 0;
+                                if is_target_visible {
+                                    let item_name = item_identification.display_name(&names.get(carried_item).unwrap().name);
+                                    game_log.entries.push(format!("The {} is drained of its charges!", item_name));
+                                }
+                            }
+                        }
+                    }
+                    EffectType::ForgetMap => {
+                        if !is_player { continue; }
+                        map.revealed_tiles = map.visible_tiles.clone();
+                        game_log.entries.push("Your memory of the level slips away!".to_string());
+                    }
+                    EffectType::Healing { amount } => {
+                        combat_stats.hp = i32::min(combat_stats.max_hp, combat_stats.hp + amount);
+                        if is_target_visible {
+                            game_log.entries.push(format!("The {} heals {} for {} hp.", effect.source, target_name, amount));
+                        }
+                    }
+                    EffectType::Identify => {
+                        // Item knowledge is the player's alone.
+                        if !is_player { continue; }
+                        let mut is_anything_identified = false;
+                        for carried_item in carried_items(&entities, &in_backpacks, target) {
+                            let name = &names.get(carried_item).unwrap().name;
+                            is_anything_identified |= identify_item(&mut item_identification, &mut game_log, name);
+                            // Curses are part of what there is to know about an item.
+                            if let Some(cursed) = cursed_components.get_mut(carried_item) {
+                                if !cursed.discovered {
+                                    cursed.discovered = true;
+                                    is_anything_identified = true;
+                                    game_log.entries.push(format!("You sense a curse on the {}.", name));
+                                }
+                            }
+                        }
+                        if !is_anything_identified {
+                            game_log.entries.push("You already know what everything you carry is.".to_string());
+                        }
+                    }
+                    EffectType::LearnSpell { name } => {
+                        // The spawner creates the spell, since it needs the whole World.
+                        wants_to_learn_spells.insert(target, WantsToLearnSpell { name: name.clone() })
+                            .expect("Unable to insert WantsToLearnSpell component.");
+                    }
+                    EffectType::MagicMapping => {
+                        if !is_player { continue; }
+                        // The main loop sweeps the map into view, a row at a time.
+                        *run_state = RunState::MagicMapReveal { row: 0 };
+                        game_log.entries.push("The layout of the level floods into your mind!".to_string());
+                    }
+                    EffectType::Recharge => {
+                        let mut is_anything_recharged = false;
+                        for carried_item in carried_items(&entities, &in_backpacks, target) {
+                            match charges_components.get_mut(carried_item) {
+                                Some(charges) if charges.current < charges.max => {
+                                    charges.current = charges.max;
+                                    is_anything_recharged = true;
+                                    if is_target_visible {
+                                        let item_name = item_identification.display_name(&names.get(carried_item).unwrap().name);
+                                        game_log.entries.push(format!("The {} is fully charged.", item_name));
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        if !is_anything_recharged && is_player {
+                            game_log.entries.push("You feel a brief tingle, but nothing else happens.".to_string());
+                        }
+                    }
+                    EffectType::RemoveCurse => {
+                        let mut is_anything_uncursed = false;
+                        for carried_item in carried_items(&entities, &in_backpacks, target) {
+                            if cursed_components.remove(carried_item).is_some() {
+                                is_anything_uncursed = true;
+                                if is_target_visible {
+                                    let item_name = item_identification.display_name(&names.get(carried_item).unwrap().name);
+                                    game_log.entries.push(format!("The {} is no longer cursed.", item_name));
+                                }
+                            }
+                        }
+                        if !is_anything_uncursed && is_player {
+                            game_log.entries.push("You feel as if someone is watching over you.".to_string());
+                        }
+                    }
+                    EffectType::Summon { name } => {
+                        let target_position = match positions.get(target) {
+                            None => continue,
+                            Some(position) => Point::new(position.x, position.y),
+                        };
+                        // The spawner does the actual summoning, since it needs the whole World.
+                        wants_to_summon_components.insert(target, WantsToSummon { name: name.clone(), position: target_position })
+                            .expect("Unable to insert WantsToSummon component.");
+                        if is_target_visible {
+                            game_log.entries.push(format!("A {} answers the summons!", name));
+                        }
+                    }
+                    EffectType::Teleport => {
+                        let destination = map.random_free_floor_tile(&mut rng);
+                        match (destination, positions.get_mut(target)) {
+                            (Some(destination), Some(target_position)) => {
+                                let old_index = map.xy_idx(target_position.x, target_position.y);
+                                let new_index = map.xy_idx(destination.x, destination.y);
+                                map.blocked_tiles[old_index] = false;
+                                map.blocked_tiles[new_index] = true;
+                                target_position.x = destination.x;
+                                target_position.y = destination.y;
+                                if let Some(viewshed) = viewsheds.get_mut(target) {
+                                    viewshed.dirty = true;
+                                }
+                                if is_player {
+                                    *player_position = destination;
+                                    game_log.entries.push("You find yourself somewhere else on the level.".to_string());
+                                } else if is_target_visible {
+                                    game_log.entries.push(format!("{} vanishes!", target_name));
+                                }
+                            }
+                            _ => {
+                                if is_player {
+                                    game_log.entries.push("You feel a wrenching sensation, but stay where you are.".to_string());
+                                }
+                            }
+                        }
+                    }
+                    EffectType::WellFed => {
+                        if let Some(hunger_clock) = hunger_clocks.get_mut(target) {
+                            hunger_clock.state = HungerState::WellFed;
+                            hunger_clock.duration = WELL_FED_TURNS;
+                            if is_player {
+                                game_log.entries.push("You feel well fed.".to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Everything "owner" carries.
+fn carried_items(entities: &Entities, in_backpacks: &ReadStorage<InBackpack>, owner: Entity) -> Vec<Entity> {
+    (entities, in_backpacks)
+        .join()
+        .filter(|(_item, in_backpack)| in_backpack.owner == owner)
+        .map(|(item, _in_backpack)| item)
+        .collect()
+}
+
+// Everything standing where the effect lands, creature or not.
+fn target_entities(map: &Map, targets: &Targets, creator: Option<Entity>) -> Vec<Entity> {
+    match targets {
+        Targets::User => creator.into_iter().collect(),
+        Targets::Entity { target } => vec![*target],
+        Targets::Tile { tile } => map.tile_contents[map.xy_idx(tile.x, tile.y)].clone(),
+        Targets::Tiles { tiles } => tiles
+            .iter()
+            .flat_map(|tile| map.tile_contents[map.xy_idx(tile.x, tile.y)].iter().copied())
+            .collect(),
+        Targets::Area { center, radius } => {
+            let mut tiles = rltk::field_of_view(*center, *radius, map);
+            tiles.retain(|point| point.x > 0 && point.x < map.width - 1 && point.y > 0 && point.y < map.height - 1);
+            target_entities(map, &Targets::Tiles { tiles }, creator)
+        }
+    }
+}
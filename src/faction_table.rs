@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Reaction {
+    Attack,
+    Ignore,
+    Flee,
+}
+
+/// How each faction reacts to creatures of the other factions.
+#[derive(Default)]
+pub struct FactionTable {
+    reactions: HashMap<String, HashMap<String, Reaction>>,
+    default_reactions: HashMap<String, Reaction>,
+}
+
+impl FactionTable {
+    // Constructor
+    pub fn new() -> FactionTable {
+        FactionTable {
+            reactions: HashMap::new(),
+            default_reactions: HashMap::new(),
+        }
+    }
+
+    /// How "faction" reacts to "other_faction".
+    pub fn add<S: ToString>(mut self, faction: S, other_faction: S, reaction: Reaction) -> FactionTable {
+        self.reactions
+            .entry(faction.to_string())
+            .or_default()
+            .insert(other_faction.to_string(), reaction);
+        self
+    }
+
+    /// How "faction" reacts to any faction it doesn't have a specific entry for.
+    pub fn add_default<S: ToString>(mut self, faction: S, reaction: Reaction) -> FactionTable {
+        self.default_reactions.insert(faction.to_string(), reaction);
+        self
+    }
+
+    pub fn reaction(&self, faction: &str, other_faction: &str) -> Reaction {
+        // Creatures never turn on their own faction.
+        if faction == other_faction { return Reaction::Ignore; }
+
+        if let Some(reactions) = self.reactions.get(faction) {
+            if let Some(reaction) = reactions.get(other_faction) {
+                return *reaction;
+            }
+        }
+        match self.default_reactions.get(faction) {
+            None => Reaction::Ignore,
+            Some(reaction) => *reaction,
+        }
+    }
+}
@@ -1,16 +1,31 @@
 use rltk::{RGB, Rltk, Point, VirtualKeyCode};
 use specs::prelude::*;
+use super::inventory_system::{carried_weight, carry_capacity, stack_name};
+use super::ranged_combat_system::shootable_targets;
+use super::shop::{buy_price, sell_price};
 use super::{
+    Charges,
     CombatStats,
+    Cursed,
     GameLog,
+    HungerClock,
+    HungerState,
     InBackpack,
+    InContainer,
+    ItemIdentification,
+    KnownSpell,
+    Mana,
     Map,
     Name,
     Player,
     Position,
+    Purse,
     RunState,
+    Stackable,
     State,
+    Value,
     Viewshed,
+    Weight,
 };
 
 
@@ -21,6 +36,43 @@ pub enum ItemMenuResult {
     Selected,
 }
 
+/// Whether the shop menu lists the shopkeeper's stock or the player's backpack.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ShopMode {
+    Buy,
+    Sell,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ShopMenuResult {
+    Cancel,
+    NoResponse,
+    Selected,
+    SwitchMode,
+}
+
+/// Which side of the container menu is picked from: the container's contents, or the player's backpack.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ContainerSide {
+    Container,
+    Backpack,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ContainerMenuResult {
+    Cancel,
+    NoResponse,
+    Selected,
+    SwitchSide,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CompanionOrderSelection {
+    Follow,
+    Stay,
+    Attack,
+}
+
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum MainMenuSelection {
     NewGame,
@@ -38,13 +90,25 @@ pub enum MainMenuResult {
     },
 }
 
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FireTargetResult {
+    Cancel,
+    NoSelection {
+        target: Entity
+    },
+    Selected {
+        target: Entity
+    },
+}
+
 pub fn draw_ui(ecs: &World, context: &mut Rltk) {
     // Box
     context.draw_box(0, 43, 79, 6, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
 
     // Depth
     let map = ecs.fetch::<Map>();
-    let depth = format!("Depth: {}", map.depth);
+    // The town is depth 0, above the dungeon.
+    let depth = if map.depth == 0 { "Town".to_string() } else { format!("Depth: {}", map.depth) };
     context.print_color(
         2, 43,
         RGB::named(rltk::YELLOW),
@@ -57,26 +121,61 @@ pub fn draw_ui(ecs: &World, context: &mut Rltk) {
     let players = ecs.read_storage::<Player>();
     for (_player, combat_stat) in (&players, &combat_stats).join() {
         // Health Text with Color
-        let health = format!("{} / {}", combat_stat.hp, combat_stat.max_hp);
+        let health = format!("HP: {} / {}", combat_stat.hp, combat_stat.max_hp);
         context.print_color(
             12, 43,
             RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
             &health);
         // Health Bar
         context.draw_bar_horizontal(
-            28, 43, 51,
+            24, 43, 14,
             combat_stat.hp, combat_stat.max_hp,
             RGB::named(rltk::RED), RGB::named(rltk::BLACK));
     }
 
+    // Mana
+    let manas = ecs.read_storage::<Mana>();
+    for (_player, mana) in (&players, &manas).join() {
+        let mana_text = format!("MP: {} / {}", mana.current, mana.max);
+        context.print_color(
+            40, 43,
+            RGB::named(rltk::CYAN), RGB::named(rltk::BLACK),
+            &mana_text);
+        context.draw_bar_horizontal(
+            52, 43, 13,
+            mana.current, mana.max,
+            RGB::named(rltk::BLUE), RGB::named(rltk::BLACK));
+    }
+
+    // Gold
+    let purses = ecs.read_storage::<Purse>();
+    for (_player, purse) in (&players, &purses).join() {
+        let gold = format!("Gold: {}", purse.gold);
+        context.print_color(
+            67, 43,
+            RGB::named(rltk::GOLD), RGB::named(rltk::BLACK),
+            &gold);
+    }
+
+    // Hunger, on the bottom edge of the box
+    let hunger_clocks = ecs.read_storage::<HungerClock>();
+    for (_player, hunger_clock) in (&players, &hunger_clocks).join() {
+        let (hunger, color) = match hunger_clock.state {
+            HungerState::WellFed => ("Well Fed", RGB::named(rltk::GREEN)),
+            HungerState::Normal => ("Normal", RGB::named(rltk::GREY)),
+            HungerState::Hungry => ("Hungry", RGB::named(rltk::ORANGE)),
+            HungerState::Starving => ("Starving", RGB::named(rltk::RED)),
+        };
+        context.print_color(
+            77 - hunger.len() as i32, 49,
+            color, RGB::named(rltk::BLACK),
+            hunger);
+    }
+
     // Game Log
     let game_log = ecs.fetch::<GameLog>();
-    let mut y_pos = 44;
-    for entry in game_log.entries.iter().rev() {
-        if y_pos < 49 {
-            context.print(2, y_pos, entry);
-        }
-        y_pos += 1;
+    for (y_pos, entry) in (44..49).zip(game_log.entries.iter().rev()) {
+        context.print(2, y_pos, entry);
     }
 
     // Mouse Cursor
@@ -88,18 +187,20 @@ pub fn draw_ui(ecs: &World, context: &mut Rltk) {
 
 fn draw_tooltips(ecs: &World, context: &mut Rltk) {
     let map = ecs.fetch::<Map>();
+    let item_identification = ecs.fetch::<ItemIdentification>();
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
+    let stackables = ecs.read_storage::<Stackable>();
 
     let (mouse_x, mouse_y) = context.mouse_pos();
     if mouse_x >= map.width || mouse_y >= map.height { return; }
     let mut tooltips: Vec<String> = Vec::new();
 
     // Gather all Entities with Names and Positions for the tooltips.
-    for (name, position) in (&names, &positions).join() {
+    for (name, position, stackable) in (&names, &positions, stackables.maybe()).join() {
         let index = map.xy_idx(position.x, position.y);
         if position.x == mouse_x && position.y == mouse_y && map.visible_tiles[index] {
-            tooltips.push(name.name.to_string());
+            tooltips.push(stack_name(item_identification.display_name(&name.name), stackable));
         }
     }
 
@@ -115,40 +216,76 @@ fn draw_tooltips(ecs: &World, context: &mut Rltk) {
         if mouse_x > 40 {
             let arrow_position = Point::new(mouse_x - 2, mouse_y);
             let left_x = mouse_x - width;
-            let mut y = mouse_y;
-            for tooltip in tooltips.iter() {
+            for (y, tooltip) in (mouse_y..).zip(tooltips.iter()) {
                 context.print_color(left_x, y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), tooltip);
                 let padding = (width - tooltip.len() as i32) - 1;
                 for i in 0..padding {
-                    context.print_color(arrow_position.x - i, y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), &" ".to_string());
+                    context.print_color(arrow_position.x - i, y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), " ");
                 }
-                y += 1;
             }
-            context.print_color(arrow_position.x, arrow_position.y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), &"->".to_string());
+            context.print_color(arrow_position.x, arrow_position.y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), "->");
         }
         // Left side of Screen
         else {
             let arrow_position = Point::new(mouse_x + 1, mouse_y);
             let left_x = mouse_x + 3;
-            let mut y = mouse_y;
-            for tooltip in tooltips.iter() {
+            for (y, tooltip) in (mouse_y..).zip(tooltips.iter()) {
                 context.print_color(left_x + 1, y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), tooltip);
                 let padding = (width - tooltip.len() as i32) - 1;
                 for i in 0..padding {
-                    context.print_color(arrow_position.x + 1 + i, y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), &" ".to_string());
+                    context.print_color(arrow_position.x + 1 + i, y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), " ");
                 }
-                y += 1;
             }
-            context.print_color(arrow_position.x, arrow_position.y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), &"<-".to_string());
+            context.print_color(arrow_position.x, arrow_position.y, RGB::named(rltk::WHITE), RGB::named(rltk::MAGENTA), "<-");
         }
     }
 }
 
 
+// How an item is listed in the inventory menus: its name as far as the player knows it,
+// how many there are, how many charges it has left, and whether it's cursed once they've found out.
+fn inventory_item_name(
+    item_identification: &ItemIdentification,
+    name: &Name,
+    stackable: Option<&Stackable>,
+    charges: Option<&Charges>,
+    cursed: Option<&Cursed>,
+) -> String {
+    let mut item_name = stack_name(item_identification.display_name(&name.name), stackable);
+    if let Some(charges) = charges {
+        item_name = format!("{} ({}/{})", item_name, charges.current, charges.max);
+    }
+    match cursed {
+        Some(cursed) if cursed.discovered => format!("{} (cursed)", item_name),
+        _ => item_name,
+    }
+}
+
+// Prints how much the player is carrying against how much they can carry, in red once it's slowing them down.
+fn print_carried_weight(ecs: &World, context: &mut Rltk, x: i32, y: i32) {
+    let player_entity = ecs.fetch::<Entity>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let in_backpacks = ecs.read_storage::<InBackpack>();
+    let stackables = ecs.read_storage::<Stackable>();
+    let weights = ecs.read_storage::<Weight>();
+
+    let capacity = match combat_stats.get(*player_entity) {
+        None => return,
+        Some(combat_stats) => carry_capacity(combat_stats),
+    };
+    let carried_weight = carried_weight(*player_entity, &in_backpacks, &weights, &stackables);
+    let color = if carried_weight > capacity { RGB::named(rltk::RED) } else { RGB::named(rltk::YELLOW) };
+    context.print_color(x, y, color, RGB::named(rltk::BLACK), format!("{:.1}/{:.1} lbs", carried_weight, capacity));
+}
+
 pub fn show_inventory(game_state: &mut State, context: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
     let entities = game_state.ecs.entities();
     let player_entity = game_state.ecs.fetch::<Entity>();
+    let item_identification = game_state.ecs.fetch::<ItemIdentification>();
     let names = game_state.ecs.read_storage::<Name>();
+    let cursed_components = game_state.ecs.read_storage::<Cursed>();
+    let stackables = game_state.ecs.read_storage::<Stackable>();
+    let charges_components = game_state.ecs.read_storage::<Charges>();
     let in_backpacks = game_state.ecs.read_storage::<InBackpack>();
 
     // Get player inventory
@@ -158,10 +295,10 @@ pub fn show_inventory(game_state: &mut State, context: &mut Rltk) -> (ItemMenuRe
     let num_items = player_inventory.count();
 
     // Draw UI
-    let mut y = (25 - (num_items / 2)) as i32;
+    let y = (25 - (num_items / 2)) as i32;
     context.draw_box(
         15, y - 2,
-        31, (num_items + 3) as i32,
+        37, (num_items + 3) as i32,
         RGB::named(rltk::WHITE),
         RGB::named(rltk::BLACK),
     );
@@ -177,21 +314,27 @@ pub fn show_inventory(game_state: &mut State, context: &mut Rltk) -> (ItemMenuRe
         RGB::named(rltk::BLACK),
         "ESC to Cancel",
     );
+    print_carried_weight(&game_state.ecs, context, 36, y + num_items as i32 + 1);
 
     let mut equippable: Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (entity, _in_backpack, name) in (&entities, &in_backpacks, &names)
+    for (j, (entity, _in_backpack, name)) in (&entities, &in_backpacks, &names)
         .join()
-        .filter(|item| item.1.owner == *player_entity) {
+        .filter(|item| item.1.owner == *player_entity)
+        .enumerate() {
+        let y = y + j as i32;
         // List out all the Items
         context.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
         context.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97 + j as rltk::FontCharType);
         context.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
 
-        context.print(21, y, &name.name.to_string());
+        context.print(21, y, inventory_item_name(
+            &item_identification,
+            name,
+            stackables.get(entity),
+            charges_components.get(entity),
+            cursed_components.get(entity),
+        ));
         equippable.push(entity);
-        y += 1;
-        j += 1;
     }
 
     match context.key {
@@ -216,16 +359,20 @@ pub fn show_inventory(game_state: &mut State, context: &mut Rltk) -> (ItemMenuRe
 pub fn drop_item_menu(gs: &mut State, context: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
     let entities = gs.ecs.entities();
     let player_entity = gs.ecs.fetch::<Entity>();
+    let item_identification = gs.ecs.fetch::<ItemIdentification>();
+    let cursed_components = gs.ecs.read_storage::<Cursed>();
+    let stackables = gs.ecs.read_storage::<Stackable>();
+    let charges_components = gs.ecs.read_storage::<Charges>();
     let in_backpacks = gs.ecs.read_storage::<InBackpack>();
     let names = gs.ecs.read_storage::<Name>();
 
     let inventory = (&in_backpacks, &names).join().filter(|item| item.0.owner == *player_entity);
     let num_items = inventory.count();
 
-    let mut y = (25 - (num_items / 2)) as i32;
+    let y = (25 - (num_items / 2)) as i32;
     context.draw_box(
         15, y - 2,
-        31, (num_items + 3) as i32,
+        37, (num_items + 3) as i32,
         RGB::named(rltk::WHITE), RGB::named(rltk::BLACK),
     );
     context.print_color(
@@ -238,12 +385,13 @@ pub fn drop_item_menu(gs: &mut State, context: &mut Rltk) -> (ItemMenuResult, Op
         RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
         "ESCAPE to cancel",
     );
+    print_carried_weight(&gs.ecs, context, 36, y + num_items as i32 + 1);
 
     let mut equippable: Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (entity, _in_backpack, name)
-    in (&entities, &in_backpacks, &names).join().filter(|item| item.1.owner == *player_entity)
+    for (j, (entity, _in_backpack, name))
+    in (&entities, &in_backpacks, &names).join().filter(|item| item.1.owner == *player_entity).enumerate()
     {
+        let y = y + j as i32;
         context.set(
             17, y,
             RGB::named(rltk::WHITE),
@@ -263,10 +411,14 @@ pub fn drop_item_menu(gs: &mut State, context: &mut Rltk) -> (ItemMenuResult, Op
             rltk::to_cp437(')'),
         );
 
-        context.print(21, y, &name.name.to_string());
+        context.print(21, y, inventory_item_name(
+            &item_identification,
+            name,
+            stackables.get(entity),
+            charges_components.get(entity),
+            cursed_components.get(entity),
+        ));
         equippable.push(entity);
-        y += 1;
-        j += 1;
     }
 
     match context.key {
@@ -286,6 +438,396 @@ pub fn drop_item_menu(gs: &mut State, context: &mut Rltk) -> (ItemMenuResult, Op
     }
 }
 
+pub fn throw_item_menu(gs: &mut State, context: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    let entities = gs.ecs.entities();
+    let player_entity = gs.ecs.fetch::<Entity>();
+    let item_identification = gs.ecs.fetch::<ItemIdentification>();
+    let cursed_components = gs.ecs.read_storage::<Cursed>();
+    let stackables = gs.ecs.read_storage::<Stackable>();
+    let charges_components = gs.ecs.read_storage::<Charges>();
+    let in_backpacks = gs.ecs.read_storage::<InBackpack>();
+    let names = gs.ecs.read_storage::<Name>();
+
+    let inventory = (&in_backpacks, &names).join().filter(|item| item.0.owner == *player_entity);
+    let num_items = inventory.count();
+
+    let y = (25 - (num_items / 2)) as i32;
+    context.draw_box(
+        15, y - 2,
+        37, (num_items + 3) as i32,
+        RGB::named(rltk::WHITE), RGB::named(rltk::BLACK),
+    );
+    context.print_color(
+        18, y - 2,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "Throw Which Item?",
+    );
+    context.print_color(
+        18, y + num_items as i32 + 1,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "ESCAPE to cancel",
+    );
+    print_carried_weight(&gs.ecs, context, 36, y + num_items as i32 + 1);
+
+    let mut equippable: Vec<Entity> = Vec::new();
+    for (j, (entity, _in_backpack, name))
+    in (&entities, &in_backpacks, &names).join().filter(|item| item.1.owner == *player_entity).enumerate()
+    {
+        let y = y + j as i32;
+        context.set(
+            17, y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('('),
+        );
+        context.set(
+            18, y,
+            RGB::named(rltk::YELLOW),
+            RGB::named(rltk::BLACK),
+            97 + j as rltk::FontCharType,
+        );
+        context.set(
+            19, y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437(')'),
+        );
+
+        context.print(21, y, inventory_item_name(
+            &item_identification,
+            name,
+            stackables.get(entity),
+            charges_components.get(entity),
+            cursed_components.get(entity),
+        ));
+        equippable.push(entity);
+    }
+
+    match context.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Escape => { (ItemMenuResult::Cancel, None) }
+                _ => {
+                    let selection = rltk::letter_to_option(key);
+                    if selection > -1 && selection < num_items as i32 {
+                        return (ItemMenuResult::Selected, Some(equippable[selection as usize]));
+                    }
+                    (ItemMenuResult::NoResponse, None)
+                }
+            }
+        }
+    }
+}
+
+pub fn spell_menu(gs: &mut State, context: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    let entities = gs.ecs.entities();
+    let player_entity = gs.ecs.fetch::<Entity>();
+    let known_spells = gs.ecs.read_storage::<KnownSpell>();
+    let manas = gs.ecs.read_storage::<Mana>();
+    let names = gs.ecs.read_storage::<Name>();
+
+    let current_mana = manas.get(*player_entity).map_or(0, |mana| mana.current);
+    let spells: Vec<(Entity, &KnownSpell, &Name)> = (&entities, &known_spells, &names)
+        .join()
+        .filter(|spell| spell.1.owner == *player_entity)
+        .collect();
+    let num_spells = spells.len();
+
+    let y = (25 - (num_spells / 2)) as i32;
+    context.draw_box(
+        15, y - 2,
+        37, (num_spells + 3) as i32,
+        RGB::named(rltk::WHITE), RGB::named(rltk::BLACK),
+    );
+    context.print_color(
+        18, y - 2,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "Cast Which Spell?",
+    );
+    context.print_color(
+        18, y + num_spells as i32 + 1,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "ESCAPE to cancel",
+    );
+    if let Some(mana) = manas.get(*player_entity) {
+        context.print_color(
+            40, y + num_spells as i32 + 1,
+            RGB::named(rltk::CYAN), RGB::named(rltk::BLACK),
+            format!("{}/{} mana", mana.current, mana.max),
+        );
+    }
+
+    for (j, (_entity, known_spell, name)) in spells.iter().enumerate() {
+        let y = y + j as i32;
+        context.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
+        context.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97 + j as rltk::FontCharType);
+        context.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+
+        // Spells the player can't afford right now are greyed out.
+        let color = if known_spell.mana_cost > current_mana { RGB::named(rltk::GREY) } else { RGB::named(rltk::WHITE) };
+        context.print_color(21, y, color, RGB::named(rltk::BLACK), format!("{} ({} mana)", name.name, known_spell.mana_cost));
+    }
+
+    match context.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Escape => { (ItemMenuResult::Cancel, None) }
+                _ => {
+                    let selection = rltk::letter_to_option(key);
+                    if selection > -1 && selection < num_spells as i32 {
+                        return (ItemMenuResult::Selected, Some(spells[selection as usize].0));
+                    }
+                    (ItemMenuResult::NoResponse, None)
+                }
+            }
+        }
+    }
+}
+
+/// Lists what the shopkeeper has for sale (or what the player could sell them) with the price of each,
+/// greying out anything the player can't afford. TAB switches between buying and selling.
+pub fn shop_menu(gs: &mut State, context: &mut Rltk, shopkeeper: Entity, mode: ShopMode) -> (ShopMenuResult, Option<Entity>) {
+    let entities = gs.ecs.entities();
+    let player_entity = gs.ecs.fetch::<Entity>();
+    let item_identification = gs.ecs.fetch::<ItemIdentification>();
+    let names = gs.ecs.read_storage::<Name>();
+    let cursed_components = gs.ecs.read_storage::<Cursed>();
+    let stackables = gs.ecs.read_storage::<Stackable>();
+    let charges_components = gs.ecs.read_storage::<Charges>();
+    let in_backpacks = gs.ecs.read_storage::<InBackpack>();
+    let purses = gs.ecs.read_storage::<Purse>();
+    let values = gs.ecs.read_storage::<Value>();
+
+    // Shops only trade in things that have a value.
+    let owner = if mode == ShopMode::Buy { shopkeeper } else { *player_entity };
+    let items: Vec<(Entity, &Name, &Value)> = (&entities, &in_backpacks, &names, &values)
+        .join()
+        .filter(|item| item.1.owner == owner)
+        .map(|(entity, _in_backpack, name, value)| (entity, name, value))
+        .collect();
+    let num_items = items.len();
+    let gold = purses.get(*player_entity).map_or(0, |purse| purse.gold);
+
+    let y = (25 - (num_items / 2)) as i32;
+    context.draw_box(
+        15, y - 2,
+        51, (num_items + 3) as i32,
+        RGB::named(rltk::WHITE), RGB::named(rltk::BLACK),
+    );
+    let shopkeeper_name = &names.get(shopkeeper).unwrap().name;
+    let title = match mode {
+        ShopMode::Buy => format!("Buy from the {}", shopkeeper_name),
+        ShopMode::Sell => format!("Sell to the {}", shopkeeper_name),
+    };
+    context.print_color(
+        18, y - 2,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        title,
+    );
+    let footer = match mode {
+        ShopMode::Buy => "ESCAPE to cancel, TAB to sell",
+        ShopMode::Sell => "ESCAPE to cancel, TAB to buy",
+    };
+    context.print_color(
+        18, y + num_items as i32 + 1,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        footer,
+    );
+    context.print_color(
+        50, y + num_items as i32 + 1,
+        RGB::named(rltk::GOLD), RGB::named(rltk::BLACK),
+        format!("{} gold", gold),
+    );
+
+    for (j, (entity, name, value)) in items.iter().enumerate() {
+        let y = y + j as i32;
+        context.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
+        context.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97 + j as rltk::FontCharType);
+        context.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+
+        let price = match mode {
+            ShopMode::Buy => buy_price(value),
+            ShopMode::Sell => sell_price(value),
+        };
+        // Things the player can't afford right now are greyed out.
+        let color = if mode == ShopMode::Buy && price > gold { RGB::named(rltk::GREY) } else { RGB::named(rltk::WHITE) };
+        context.print_color(21, y, color, RGB::named(rltk::BLACK), inventory_item_name(
+            &item_identification,
+            name,
+            stackables.get(*entity),
+            charges_components.get(*entity),
+            cursed_components.get(*entity),
+        ));
+        context.print_color(55, y, RGB::named(rltk::GOLD), RGB::named(rltk::BLACK), format!("{:>4} gold", price));
+    }
+
+    match context.key {
+        None => (ShopMenuResult::NoResponse, None),
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Escape => { (ShopMenuResult::Cancel, None) }
+                VirtualKeyCode::Tab => { (ShopMenuResult::SwitchMode, None) }
+                _ => {
+                    let selection = rltk::letter_to_option(key);
+                    if selection > -1 && selection < num_items as i32 {
+                        return (ShopMenuResult::Selected, Some(items[selection as usize].0));
+                    }
+                    (ShopMenuResult::NoResponse, None)
+                }
+            }
+        }
+    }
+}
+
+// How wide each column of the container menu is, so long names don't run into the other side.
+const CONTAINER_COLUMN_WIDTH: usize = 30;
+
+pub fn container_menu(gs: &mut State, context: &mut Rltk, container: Entity, side: ContainerSide) -> (ContainerMenuResult, Option<Entity>) {
+    let entities = gs.ecs.entities();
+    let player_entity = gs.ecs.fetch::<Entity>();
+    let item_identification = gs.ecs.fetch::<ItemIdentification>();
+    let names = gs.ecs.read_storage::<Name>();
+    let cursed_components = gs.ecs.read_storage::<Cursed>();
+    let stackables = gs.ecs.read_storage::<Stackable>();
+    let charges_components = gs.ecs.read_storage::<Charges>();
+    let in_backpacks = gs.ecs.read_storage::<InBackpack>();
+    let in_containers = gs.ecs.read_storage::<InContainer>();
+
+    let contents: Vec<(Entity, &Name)> = (&entities, &in_containers, &names)
+        .join()
+        .filter(|item| item.1.owner == container)
+        .map(|(entity, _in_container, name)| (entity, name))
+        .collect();
+    let backpack: Vec<(Entity, &Name)> = (&entities, &in_backpacks, &names)
+        .join()
+        .filter(|item| item.1.owner == *player_entity)
+        .map(|(entity, _in_backpack, name)| (entity, name))
+        .collect();
+    // Both sides are listed side by side, so the box fits whichever is longer.
+    let num_rows = usize::max(1, usize::max(contents.len(), backpack.len()));
+
+    let y = (25 - (num_rows / 2)) as i32;
+    context.draw_box(
+        2, y - 2,
+        75, (num_rows + 3) as i32,
+        RGB::named(rltk::WHITE), RGB::named(rltk::BLACK),
+    );
+    for divider_y in y - 1..y + num_rows as i32 + 1 {
+        context.set(39, divider_y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('│'));
+    }
+    let footer = match side {
+        ContainerSide::Container => "ESCAPE to close, TAB to put things in",
+        ContainerSide::Backpack => "ESCAPE to close, TAB to take things out",
+    };
+    context.print_color(
+        5, y + num_rows as i32 + 1,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        footer,
+    );
+
+    let columns = [
+        (4, names.get(container).unwrap().name.as_str(), &contents, side == ContainerSide::Container),
+        (41, "Backpack", &backpack, side == ContainerSide::Backpack),
+    ];
+    for (x, title, items, is_selected) in columns {
+        // Only the side being picked from is lettered; the other is greyed out.
+        let (title_color, item_color) = if is_selected {
+            (RGB::named(rltk::YELLOW), RGB::named(rltk::WHITE))
+        } else {
+            (RGB::named(rltk::GREY), RGB::named(rltk::GREY))
+        };
+        context.print_color(x + 1, y - 2, title_color, RGB::named(rltk::BLACK), title);
+        if items.is_empty() {
+            context.print_color(x + 4, y, RGB::named(rltk::GREY), RGB::named(rltk::BLACK), "(empty)");
+        }
+        for (j, (entity, name)) in items.iter().enumerate() {
+            let y = y + j as i32;
+            if is_selected {
+                context.set(x, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
+                context.set(x + 1, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97 + j as rltk::FontCharType);
+                context.set(x + 2, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+            }
+            let item_name: String = inventory_item_name(
+                &item_identification,
+                name,
+                stackables.get(*entity),
+                charges_components.get(*entity),
+                cursed_components.get(*entity),
+            ).chars().take(CONTAINER_COLUMN_WIDTH).collect();
+            context.print_color(x + 4, y, item_color, RGB::named(rltk::BLACK), item_name);
+        }
+    }
+
+    let items = if side == ContainerSide::Container { &contents } else { &backpack };
+    match context.key {
+        None => (ContainerMenuResult::NoResponse, None),
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Escape => { (ContainerMenuResult::Cancel, None) }
+                VirtualKeyCode::Tab => { (ContainerMenuResult::SwitchSide, None) }
+                _ => {
+                    let selection = rltk::letter_to_option(key);
+                    if selection > -1 && selection < items.len() as i32 {
+                        return (ContainerMenuResult::Selected, Some(items[selection as usize].0));
+                    }
+                    (ContainerMenuResult::NoResponse, None)
+                }
+            }
+        }
+    }
+}
+
+pub fn companion_orders_menu(context: &mut Rltk) -> (ItemMenuResult, Option<CompanionOrderSelection>) {
+    let orders = [
+        ("Follow me", CompanionOrderSelection::Follow),
+        ("Stay here", CompanionOrderSelection::Stay),
+        ("Attack target", CompanionOrderSelection::Attack),
+    ];
+
+    let y = (25 - (orders.len() / 2)) as i32;
+    context.draw_box(
+        15, y - 2,
+        31, (orders.len() + 3) as i32,
+        RGB::named(rltk::WHITE), RGB::named(rltk::BLACK),
+    );
+    context.print_color(
+        18, y - 2,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "Order Companions",
+    );
+    context.print_color(
+        18, y + orders.len() as i32 + 1,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "ESCAPE to cancel",
+    );
+
+    for (j, (description, _order)) in orders.iter().enumerate() {
+        let y = y + j as i32;
+        context.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
+        context.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97 + j as rltk::FontCharType);
+        context.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+        context.print(21, y, description);
+    }
+
+    match context.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Escape => { (ItemMenuResult::Cancel, None) }
+                _ => {
+                    let selection = rltk::letter_to_option(key);
+                    if selection > -1 && selection < orders.len() as i32 {
+                        return (ItemMenuResult::Selected, Some(orders[selection as usize].1));
+                    }
+                    (ItemMenuResult::NoResponse, None)
+                }
+            }
+        }
+    }
+}
+
 pub fn ranged_target(game_state: &mut State, context: &mut Rltk, range: i32) -> (ItemMenuResult, Option<Point>) {
     let player_entity = game_state.ecs.fetch::<Entity>();
     let player_position = game_state.ecs.fetch::<Point>();
@@ -334,6 +876,53 @@ pub fn ranged_target(game_state: &mut State, context: &mut Rltk, range: i32) ->
     (ItemMenuResult::NoResponse, None)
 }
 
+/// Picks what to shoot at, cycling through the targets in range with the keyboard.
+pub fn fire_target(game_state: &mut State, context: &mut Rltk, range: i32, target: Entity) -> FireTargetResult {
+    let targets = shootable_targets(&game_state.ecs, range);
+    if targets.is_empty() { return FireTargetResult::Cancel; }
+    // The last target may have died or moved out of sight; if so, start again from the closest.
+    let index = targets.iter().position(|candidate| *candidate == target).unwrap_or(0);
+
+    context.print_color(
+        5, 0,
+        RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "Select Target: TAB to cycle, F or ENTER to fire, ESCAPE to cancel",
+    );
+
+    // Highlight the targets, and the path the shot will take.
+    let map = game_state.ecs.fetch::<Map>();
+    let player_position = *game_state.ecs.fetch::<Point>();
+    let positions = game_state.ecs.read_storage::<Position>();
+    for (i, candidate) in targets.iter().enumerate() {
+        let position = positions.get(*candidate).unwrap();
+        if i == index {
+            for point in map.projectile_path(player_position, Point::new(position.x, position.y)) {
+                context.set_bg(point.x, point.y, RGB::named(rltk::BLUE));
+            }
+            context.set_bg(position.x, position.y, RGB::named(rltk::CYAN));
+        } else {
+            context.set_bg(position.x, position.y, RGB::named(rltk::DARK_BLUE));
+        }
+    }
+
+    match context.key {
+        None => FireTargetResult::NoSelection { target: targets[index] },
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Escape => FireTargetResult::Cancel,
+                VirtualKeyCode::Tab | VirtualKeyCode::Right | VirtualKeyCode::Down => {
+                    FireTargetResult::NoSelection { target: targets[(index + 1) % targets.len()] }
+                }
+                VirtualKeyCode::Left | VirtualKeyCode::Up => {
+                    FireTargetResult::NoSelection { target: targets[(index + targets.len() - 1) % targets.len()] }
+                }
+                VirtualKeyCode::F | VirtualKeyCode::Return => FireTargetResult::Selected { target: targets[index] },
+                _ => FireTargetResult::NoSelection { target: targets[index] },
+            }
+        }
+    }
+}
+
 pub fn main_menu(game_state: &mut State, context: &mut Rltk) -> MainMenuResult {
     let save_exists = super::save_load_system::does_save_exist();
     let current_run_state = game_state.ecs.fetch::<RunState>();
@@ -371,21 +960,19 @@ pub fn main_menu(game_state: &mut State, context: &mut Rltk) -> MainMenuResult {
                         };
                     }
                     VirtualKeyCode::Up => {
-                        let new_main_menu_selection;
-                        match current_main_menu_selection {
-                            MainMenuSelection::NewGame => new_main_menu_selection = MainMenuSelection::Quit,
-                            MainMenuSelection::LoadGame => new_main_menu_selection = MainMenuSelection::NewGame,
-                            MainMenuSelection::Quit => new_main_menu_selection = MainMenuSelection::LoadGame
-                        }
+                        let new_main_menu_selection = match current_main_menu_selection {
+                            MainMenuSelection::NewGame => MainMenuSelection::Quit,
+                            MainMenuSelection::LoadGame => MainMenuSelection::NewGame,
+                            MainMenuSelection::Quit => MainMenuSelection::LoadGame
+                        };
                         return MainMenuResult::NoSelection { selected: new_main_menu_selection };
                     }
                     VirtualKeyCode::Down => {
-                        let new_main_menu_selection;
-                        match current_main_menu_selection {
-                            MainMenuSelection::NewGame => new_main_menu_selection = MainMenuSelection::LoadGame,
-                            MainMenuSelection::LoadGame => new_main_menu_selection = MainMenuSelection::Quit,
-                            MainMenuSelection::Quit => new_main_menu_selection = MainMenuSelection::NewGame
-                        }
+                        let new_main_menu_selection = match current_main_menu_selection {
+                            MainMenuSelection::NewGame => MainMenuSelection::LoadGame,
+                            MainMenuSelection::LoadGame => MainMenuSelection::Quit,
+                            MainMenuSelection::Quit => MainMenuSelection::NewGame
+                        };
                         return MainMenuResult::NoSelection { selected: new_main_menu_selection };
                     }
                     VirtualKeyCode::Return => return MainMenuResult::Selected { selected: current_main_menu_selection },
@@ -0,0 +1,64 @@
+use specs::prelude::*;
+use crate::effects_system::{EffectQueue, EffectType, Targets};
+use super::{GameLog, HungerClock, HungerState, RunState};
+
+// How many of the player's turns each state lasts before they get hungrier.
+pub const WELL_FED_TURNS: i32 = 100;
+const NORMAL_TURNS: i32 = 300;
+const HUNGRY_TURNS: i32 = 200;
+// Starving hurts this much every turn, until the player eats something.
+const STARVING_DAMAGE: i32 = 1;
+
+pub struct HungerSystem {}
+
+impl<'a> System<'a> for HungerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, HungerClock>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, player_entity, run_state, mut effect_queue, mut game_log, mut hunger_clocks) = data;
+        // Only the player's turns count, so opening a menu doesn't make anyone hungrier.
+        if *run_state != RunState::PlayerTurn { return; }
+
+        for (entity, hunger_clock) in (&entities, &mut hunger_clocks).join() {
+            let is_player = entity == *player_entity;
+            if hunger_clock.state == HungerState::Starving {
+                effect_queue.add(None, "hunger", EffectType::Damage { amount: STARVING_DAMAGE }, Targets::Entity { target: entity });
+                continue;
+            }
+
+            hunger_clock.duration -= 1;
+            if hunger_clock.duration > 0 { continue; }
+            match hunger_clock.state {
+                HungerState::WellFed => {
+                    hunger_clock.state = HungerState::Normal;
+                    hunger_clock.duration = NORMAL_TURNS;
+                    if is_player {
+                        game_log.entries.push("You are no longer well fed.".to_string());
+                    }
+                }
+                HungerState::Normal => {
+                    hunger_clock.state = HungerState::Hungry;
+                    hunger_clock.duration = HUNGRY_TURNS;
+                    if is_player {
+                        game_log.entries.push("You are hungry.".to_string());
+                    }
+                }
+                HungerState::Hungry => {
+                    hunger_clock.state = HungerState::Starving;
+                    if is_player {
+                        game_log.entries.push("You are starving! Eat something before it kills you.".to_string());
+                    }
+                }
+                HungerState::Starving => {}
+            }
+        }
+    }
+}
@@ -1,58 +1,233 @@
+use std::ops::Deref;
+
+use rltk::Point;
 use specs::prelude::*;
-use crate::{AreaOfEffect, Confusion, InflictsDamage, SufferDamage};
+use specs::storage::MaskedStorage;
+use crate::{AreaOfEffect, Confusion, InflictsDamage};
+use crate::effects_system::{EffectQueue, EffectType, Targets};
+use crate::ranged_combat_system::ProjectileAnimation;
 use super::{
+    Charges,
+    Charms,
     CombatStats,
     Consumable,
+    Cursed,
+    DetectItems,
+    DetectMonsters,
+    Encumbered,
     GameLog,
+    Gold,
+    Identifies,
     InBackpack,
+    InContainer,
+    Item,
+    ItemIdentification,
+    KnownSpell,
+    MagicMapper,
+    Mana,
     Map,
     Name,
     Position,
+    ProvidesFood,
     ProvidesHealing,
+    Purse,
+    Recharges,
+    RemovesCurse,
+    Renderer,
+    Shatters,
+    Stackable,
+    Summons,
+    TeachesSpell,
+    Teleports,
     WantsToDropItem,
     WantsToPickupItem,
+    WantsToThrowItem,
     WantsToUseItem,
+    Weight,
 };
 
+// The player can carry this many pounds, plus this many per point of power, before slowing down...
+const BASE_CARRY_CAPACITY: f32 = 5.0;
+const CARRY_CAPACITY_PER_POWER: f32 = 2.0;
+// ...and won't pick up anything that would take them past this multiple of it.
+const MAX_LOAD_MULTIPLIER: f32 = 1.5;
+
+/// How many pounds the player can carry before they're encumbered.
+pub fn carry_capacity(combat_stats: &CombatStats) -> f32 {
+    BASE_CARRY_CAPACITY + CARRY_CAPACITY_PER_POWER * combat_stats.power as f32
+}
+
+/// The most the player can carry at all.
+pub fn max_load(combat_stats: &CombatStats) -> f32 {
+    carry_capacity(combat_stats) * MAX_LOAD_MULTIPLIER
+}
+
+/// The weight of a whole stack (or of a single item).
+pub fn stack_weight(weight: &Weight, stackable: Option<&Stackable>) -> f32 {
+    weight.pounds * stackable.map_or(1, |stackable| stackable.quantity) as f32
+}
+
+/// How many pounds "owner" is carrying in their backpack.
+pub fn carried_weight<B, W, S>(
+    owner: Entity,
+    in_backpacks: &Storage<InBackpack, B>,
+    weights: &Storage<Weight, W>,
+    stackables: &Storage<Stackable, S>,
+) -> f32
+where
+    B: Deref<Target = MaskedStorage<InBackpack>>,
+    W: Deref<Target = MaskedStorage<Weight>>,
+    S: Deref<Target = MaskedStorage<Stackable>>,
+{
+    (in_backpacks, weights, stackables.maybe())
+        .join()
+        .filter(|(in_backpack, _weight, _stackable)| in_backpack.owner == owner)
+        .map(|(_in_backpack, weight, stackable)| stack_weight(weight, stackable))
+        .sum()
+}
+
+/// Whether "owner" has room in their backpack for this many more pounds.
+pub fn can_carry(ecs: &World, owner: Entity, pounds: f32) -> bool {
+    let combat_stats_components = ecs.read_storage::<CombatStats>();
+    let in_backpacks = ecs.read_storage::<InBackpack>();
+    let stackables = ecs.read_storage::<Stackable>();
+    let weights = ecs.read_storage::<Weight>();
+    let combat_stats = match combat_stats_components.get(owner) {
+        None => return true,
+        Some(combat_stats) => combat_stats,
+    };
+    carried_weight(owner, &in_backpacks, &weights, &stackables) + pounds <= max_load(combat_stats)
+}
+
 pub struct ItemCollectionSystem {}
 
 impl<'a> System<'a> for ItemCollectionSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = (
+        Entities<'a>,
         ReadExpect<'a, Entity>,
+        ReadExpect<'a, ItemIdentification>,
+        ReadExpect<'a, Map>,
         WriteExpect<'a, GameLog>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Cursed>,
+        ReadStorage<'a, Gold>,
         ReadStorage<'a, Name>,
+        ReadStorage<'a, Weight>,
         WriteStorage<'a, InBackpack>,
+        WriteStorage<'a, InContainer>,
         WriteStorage<'a, Position>,
+        WriteStorage<'a, Purse>,
+        WriteStorage<'a, Stackable>,
         WriteStorage<'a, WantsToPickupItem>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
+            entities,
             player_entity,
+            item_identification,
+            map,
             mut game_log,
+            combat_stats_components,
+            cursed_components,
+            gold_components,
             names,
+            weights,
             mut in_backpacks,
+            mut in_containers,
             mut positions,
+            mut purses,
+            mut stackables,
             mut wants_to_pickup_items
         ) = data;
 
         for wants_to_pickup_item in wants_to_pickup_items.join() {
+            // The player can only carry so much.
+            if wants_to_pickup_item.collected_by == *player_entity {
+                let combat_stats = combat_stats_components.get(wants_to_pickup_item.collected_by);
+                if let (Some(weight), Some(combat_stats)) = (weights.get(wants_to_pickup_item.item), combat_stats) {
+                    let carried_weight = carried_weight(wants_to_pickup_item.collected_by, &in_backpacks, &weights, &stackables);
+                    if carried_weight + stack_weight(weight, stackables.get(wants_to_pickup_item.item)) > max_load(combat_stats) {
+                        let item_name = item_identification.display_name(&names.get(wants_to_pickup_item.item).unwrap().name);
+                        game_log.entries.push(format!("You can't carry the {} as well. Your pack is too heavy.", item_name));
+                        continue;
+                    }
+                }
+            }
+
             // Remove the Item from the World by remove it's Position component.
-            positions.remove(wants_to_pickup_item.item);
+            let item_position = positions.remove(wants_to_pickup_item.item);
 
-            // Create InBackpack component
-            in_backpacks.insert(
-                wants_to_pickup_item.item,
-                InBackpack {
-                    owner: wants_to_pickup_item.collected_by
-                },
-            ).expect("Unable to insert backpack entry");
+            // Gold goes straight into the collector's purse, if they have one.
+            if let Some(gold) = gold_components.get(wants_to_pickup_item.item) {
+                if let Some(purse) = purses.get_mut(wants_to_pickup_item.collected_by) {
+                    purse.gold += gold.amount;
+                    if wants_to_pickup_item.collected_by == *player_entity {
+                        game_log.entries.push(format!("You pick up {} gold.", gold.amount));
+                    }
+                    entities.delete(wants_to_pickup_item.item).expect("Delete Entity Failed.");
+                    continue;
+                }
+            }
 
             // If the player picked up the item, log it.
+            let item_name = stack_name(
+                item_identification.display_name(&names.get(wants_to_pickup_item.item).unwrap().name),
+                stackables.get(wants_to_pickup_item.item),
+            );
+
+            // Stackable Items join a matching stack in the backpack if there is one.
+            let existing_stack = matching_stack(
+                &entities,
+                &cursed_components,
+                &in_backpacks,
+                &names,
+                &stackables,
+                wants_to_pickup_item.item,
+                |in_backpack| in_backpack.owner == wants_to_pickup_item.collected_by,
+            );
+            match existing_stack {
+                Some(existing_stack) => {
+                    let picked_up_quantity = stackables.get(wants_to_pickup_item.item).unwrap().quantity;
+                    stackables.get_mut(existing_stack).unwrap().quantity += picked_up_quantity;
+                    entities.delete(wants_to_pickup_item.item).expect("Delete Entity Failed.");
+                }
+                None => {
+                    // Create InBackpack component
+                    in_backpacks.insert(
+                        wants_to_pickup_item.item,
+                        InBackpack {
+                            owner: wants_to_pickup_item.collected_by
+                        },
+                    ).expect("Unable to insert backpack entry");
+                }
+            }
+
             if wants_to_pickup_item.collected_by == *player_entity {
-                let item_name = &names.get(wants_to_pickup_item.item).unwrap().name;
                 game_log.entries.push(format!("You pick up the {}", item_name));
+            } else if let Some(item_position) = &item_position {
+                // Monsters pick things up too; mention it if the player saw it happen.
+                if map.visible_tiles[map.xy_idx(item_position.x, item_position.y)] {
+                    let collector_name = &names.get(wants_to_pickup_item.collected_by).unwrap().name;
+                    game_log.entries.push(format!("{} picks up the {}.", collector_name, item_name));
+                }
+            }
+
+            // Picking up a container, like a corpse, spills whatever was inside onto the floor.
+            if let Some(item_position) = item_position {
+                let contents: Vec<Entity> = (&entities, &in_containers)
+                    .join()
+                    .filter(|(_content, in_container)| in_container.owner == wants_to_pickup_item.item)
+                    .map(|(content, _in_container)| content)
+                    .collect();
+                for content in contents.iter() {
+                    in_containers.remove(*content);
+                    positions.insert(*content, item_position.clone()).expect("Unable to insert Position component.");
+                }
+                if !contents.is_empty() && wants_to_pickup_item.collected_by == *player_entity {
+                    game_log.entries.push(format!("Everything inside the {} spills onto the floor.", item_name));
+                }
             }
         }
 
@@ -68,34 +243,80 @@ impl<'a> System<'a> for ItemUseSystem {
     type SystemData = (
         Entities<'a>,
         ReadExpect<'a, Entity>,
-        ReadExpect<'a, Map>,
+        WriteExpect<'a, EffectQueue>,
         WriteExpect<'a, GameLog>,
+        WriteExpect<'a, ItemIdentification>,
+        ReadExpect<'a, Map>,
         ReadStorage<'a, AreaOfEffect>,
+        ReadStorage<'a, Charms>,
+        ReadStorage<'a, Confusion>,
         ReadStorage<'a, Consumable>,
         ReadStorage<'a, InflictsDamage>,
         ReadStorage<'a, Name>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, ProvidesFood>,
         ReadStorage<'a, ProvidesHealing>,
-        WriteStorage<'a, CombatStats>,
-        WriteStorage<'a, Confusion>,
-        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Summons>,
         WriteStorage<'a, WantsToUseItem>,
+        // Everything to do with the items themselves, rather than their effect on the targets.
+        (
+            ReadStorage<'a, Identifies>,
+            ReadStorage<'a, Item>,
+            ReadStorage<'a, KnownSpell>,
+            ReadStorage<'a, Recharges>,
+            ReadStorage<'a, RemovesCurse>,
+            ReadStorage<'a, TeachesSpell>,
+            WriteStorage<'a, Charges>,
+            WriteStorage<'a, Cursed>,
+            WriteStorage<'a, Mana>,
+            WriteStorage<'a, Stackable>,
+        ),
+        // Effects on the level as a whole, or on where the user is in it.
+        (
+            ReadStorage<'a, DetectItems>,
+            ReadStorage<'a, DetectMonsters>,
+            ReadStorage<'a, MagicMapper>,
+            ReadStorage<'a, Teleports>,
+        ),
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
             entities,
             player_entity,
-            map,
+            mut effect_queue,
             mut game_log,
+            mut item_identification,
+            map,
             area_of_effect_components,
+            charms_components,
+            confusion_components,
             consumables,
             inflicts_damage_components,
             names,
+            positions,
+            provides_food_components,
             provides_healing_components,
-            mut combat_stats_components,
-            mut confusion_components,
-            mut suffer_damage_components,
-            mut wants_to_use_item_components
+            summons_components,
+            mut wants_to_use_item_components,
+            (
+                identifies_components,
+                items,
+                known_spells,
+                recharges_components,
+                removes_curse_components,
+                teaches_spell_components,
+                mut charges_components,
+                mut cursed_components,
+                mut manas,
+                mut stackables,
+            ),
+            (
+                detect_items_components,
+                detect_monsters_components,
+                magic_mappers,
+                teleports_components,
+            ),
         ) = data;
 
         for (entity, wants_to_use_item_component)
@@ -104,145 +325,194 @@ impl<'a> System<'a> for ItemUseSystem {
             let mut is_item_used = true;
             let item_entity = wants_to_use_item_component.item;
 
-            // Targeting Items
-            let mut targets: Vec<Entity> = Vec::new();
-            match wants_to_use_item_component.target {
-                None => {
-                    // No target, so apply to player.
-                    targets.push(*player_entity);
-                }
-                Some(target) => {
-                    // There is a point specified. Check for AreaOfEffect component.
-                    let possible_area_of_affect_component
-                        = area_of_effect_components.get(item_entity);
-                    match possible_area_of_affect_component {
-                        None => {
-                            // Single (Tile) Target
-                            let index = map.xy_idx(target.x, target.y);
-                            for target in map.tile_contents[index].iter() {
-                                targets.push(*target);
-                            }
-                        }
-                        Some(area_of_effect_component) => {
-                            // AoE Target
-                            let mut affected_tiles = rltk::field_of_view(
-                                target,
-                                area_of_effect_component.radius,
-                                &*map,
-                            );
-                            affected_tiles.retain(|point|
-                                point.x > 0 &&
-                                    point.x < map.width - 1 &&
-                                    point.y > 0 &&
-                                    point.y < map.height - 1
-                            );
-                            for affected_tile in affected_tiles.iter() {
-                                let index = map.xy_idx(affected_tile.x, affected_tile.y);
-                                for mob in map.tile_contents[index].iter() {
-                                    // Right now this allows you to do AoE damage to items, even though they don't have health.
-                                    targets.push(*mob);
-                                }
-                            }
+            // Monsters use abilities through here too, so only log what the player can see.
+            let mut is_user_visible = entity == *player_entity;
+            if let Some(user_position) = positions.get(entity) {
+                is_user_visible |= map.visible_tiles[map.xy_idx(user_position.x, user_position.y)];
+            }
+            let user_name = &names.get(entity).unwrap().name;
+            let is_spell = known_spells.contains(item_entity);
+            // Spells are cast, wands and rods are zapped, food is eaten, and anything else is just used.
+            let (verb, verbs) = if is_spell {
+                ("cast", "casts")
+            } else if charges_components.contains(item_entity) {
+                ("zap", "zaps")
+            } else if provides_food_components.contains(item_entity) {
+                ("eat", "eats")
+            } else {
+                ("use", "uses")
+            };
+            let user_description = if entity == *player_entity {
+                format!("You {}", verb)
+            } else {
+                format!("{} {}", user_name, verbs)
+            };
+
+            // Spells cost mana, and fizzle without enough of it.
+            if let Some(known_spell) = known_spells.get(item_entity) {
+                match manas.get_mut(entity) {
+                    Some(mana) if mana.current >= known_spell.mana_cost => mana.current -= known_spell.mana_cost,
+                    _ => {
+                        if entity == *player_entity {
+                            let spell_name = &names.get(item_entity).unwrap().name;
+                            game_log.entries.push(format!("You don't have enough mana to cast {}.", spell_name));
                         }
+                        continue;
                     }
                 }
             }
 
-            // Healing Items
-            let healing_item = provides_healing_components.get(item_entity);
-            match healing_item {
-                None => {}
-                Some(healing_item) => {
-                    is_item_used = false;
-                    for target in targets.iter() {
-                        // Try get CombatStats component.
-                        let combat_stats_component = combat_stats_components.get_mut(*target);
-                        if let Some(combat_stats_component) = combat_stats_component {
-                            // Restore HP
-                            combat_stats_component.hp = i32::min(
-                                combat_stats_component.max_hp,
-                                combat_stats_component.hp + healing_item.heal_amount,
-                            );
-                            // If player, log the interaction.
-                            if entity == *player_entity {
-                                let potion_name = &names.get(wants_to_use_item_component.item).unwrap().name;
-                                game_log.entries.push(format!("You drink the {}, healing {} hp.", potion_name, healing_item.heal_amount));
-                            }
-                            is_item_used = true;
-                        }
+            // Charged Items do nothing once they've run out.
+            if charges_components.get(item_entity).is_some_and(|charges| charges.current <= 0) {
+                if is_user_visible {
+                    let item_name = item_identification.display_name(&names.get(item_entity).unwrap().name);
+                    if entity == *player_entity {
+                        game_log.entries.push(format!("You zap the {}, but nothing happens.", item_name));
+                    } else {
+                        game_log.entries.push(format!("{} zaps the {}, but nothing happens.", user_name, item_name));
                     }
                 }
+                continue;
             }
 
-            // Inflicts Damage Items
-            let inflicts_damage_item = inflicts_damage_components.get(item_entity);
-            match inflicts_damage_item {
-                None => {}
-                Some(inflicts_damage_item) => {
-                    // is_item_used = false;
-                    for target in targets.iter() {
-                        // Add damage
-                        SufferDamage::new_damage(
-                            &mut suffer_damage_components,
-                            *target,
-                            inflicts_damage_item.damage,
-                        );
-                        if entity == *player_entity {
-                            let target_name = names.get(*target).unwrap();
-                            let item_name = names.get(item_entity).unwrap();
-                            game_log.entries.push(
-                                format!(
-                                    "You use {} on {}, inflicting {} damage.",
-                                    item_name.name,
-                                    target_name.name,
-                                    inflicts_damage_item.damage,
-                                )
-                            );
-                        }
+            // Seeing an item used is enough to know what it is.
+            if is_user_visible {
+                identify_item(&mut item_identification, &mut game_log, &names.get(item_entity).unwrap().name);
+                // Spells and abilities go without "the".
+                let item_name = item_identification.display_name(&names.get(item_entity).unwrap().name);
+                let item_description = if items.contains(item_entity) { format!("the {}", item_name) } else { item_name };
+                game_log.entries.push(format!("{} {}.", user_description, item_description));
+            }
+
+            // Cursed Items turn on their user: anything aimed lands on them instead, and the rest goes wrong.
+            let is_cursed = cursed_components.contains(item_entity);
+            if is_cursed && is_user_visible {
+                cursed_components.get_mut(item_entity).unwrap().discovered = true;
+                let item_name = item_identification.display_name(&names.get(item_entity).unwrap().name);
+                game_log.entries.push(format!("The {} is cursed!", item_name));
+            }
+            let target_point = match (is_cursed, wants_to_use_item_component.target, positions.get(entity)) {
+                (true, Some(_target), Some(user_position)) => Some(rltk::Point::new(user_position.x, user_position.y)),
+                (_, target, _) => target,
+            };
+
+            // Effects are queued, for the EffectsSystem to apply to whoever is where they land.
+            let source = item_identification.display_name(&names.get(item_entity).unwrap().name);
+
+            // Identifying Items
+            if identifies_components.contains(item_entity) && !is_cursed {
+                effect_queue.add(Some(entity), &source, EffectType::Identify, Targets::User);
+            }
+
+            // Remove Curse Items
+            if removes_curse_components.contains(item_entity) && !is_cursed {
+                effect_queue.add(Some(entity), &source, EffectType::RemoveCurse, Targets::User);
+            }
+
+            // Recharging Items (a cursed one drains the charges instead)
+            if recharges_components.contains(item_entity) {
+                effect_queue.add(Some(entity), &source, if is_cursed { EffectType::DrainCharges } else { EffectType::Recharge }, Targets::User);
+            }
+
+            // Spell Teaching Items (a cursed one garbles the spell instead)
+            if let Some(teaches_spell) = teaches_spell_components.get(item_entity) {
+                let is_spell_known = (&known_spells, &names)
+                    .join()
+                    .any(|(known_spell, name)| known_spell.owner == entity && name.name == teaches_spell.name);
+                if is_cursed {
+                    if entity == *player_entity {
+                        game_log.entries.push("The words twist away from you as you read them.".to_string());
+                    }
+                } else if is_spell_known {
+                    // There's nothing to learn, so the scroll is kept.
+                    is_item_used = false;
+                    if entity == *player_entity {
+                        game_log.entries.push(format!("You already know the {} spell.", teaches_spell.name));
                     }
-                    is_item_used = true;
+                } else {
+                    effect_queue.add(Some(entity), &source, EffectType::LearnSpell { name: teaches_spell.name.clone() }, Targets::User);
                 }
             }
 
-            // Confusion Items
-            let mut confused_targets = Vec::new();
-            {
-                let causes_confusion_item = confusion_components.get(item_entity);
-                match causes_confusion_item {
-                    None => {}
-                    Some(causes_confusion_item) => {
-                        // is_item_used = false;
-                        for target in targets.iter() {
-                            confused_targets.push((*target, causes_confusion_item.turns));
-                            if entity == *player_entity {
-                                let target_name = &names.get(*target).unwrap().name;
-                                let item_name = &names.get(item_entity).unwrap().name;
-                                game_log.entries.push(format!(
-                                    "You use {} on {}, confusing them!",
-                                    item_name,
-                                    target_name
-                                ));
-                            }
-                        }
-                        is_item_used = true;
-                    }
+            // Food Items
+            if provides_food_components.contains(item_entity) {
+                effect_queue.add(Some(entity), &source, EffectType::WellFed, Targets::User);
+            }
+
+            // Magic Mapping Items (a cursed one makes the player forget the level instead)
+            if magic_mappers.contains(item_entity) {
+                effect_queue.add(Some(entity), &source, if is_cursed { EffectType::ForgetMap } else { EffectType::MagicMapping }, Targets::User);
+            }
+
+            // Teleporting Items
+            if teleports_components.contains(item_entity) {
+                effect_queue.add(Some(entity), &source, EffectType::Teleport, Targets::User);
+            }
+
+            // Detection Items (a cursed one does nothing)
+            let detect_items_turns = detect_items_components.get(item_entity).map(|detect_items| detect_items.turns);
+            let detect_monsters_turns = detect_monsters_components.get(item_entity).map(|detect_monsters| detect_monsters.turns);
+            if is_cursed && (detect_items_turns.is_some() || detect_monsters_turns.is_some()) {
+                if entity == *player_entity {
+                    game_log.entries.push("Your senses dull for a moment.".to_string());
+                }
+            } else {
+                if let Some(turns) = detect_items_turns {
+                    effect_queue.add(Some(entity), &source, EffectType::DetectItems { turns }, Targets::User);
+                }
+                if let Some(turns) = detect_monsters_turns {
+                    effect_queue.add(Some(entity), &source, EffectType::DetectMonsters { turns }, Targets::User);
                 }
             }
-            for target in confused_targets.iter() {
-                confusion_components.insert(
-                    target.0,
-                    Confusion {
-                        turns: target.1
-                    },
-                ).expect("Unable to insert Confusion component.");
+
+            // Effects on creatures land on whoever is at the target, or on the user when there's none.
+            let targets = match (target_point, area_of_effect_components.get(item_entity)) {
+                (None, _) => Targets::User,
+                (Some(target), None) => Targets::Tile { tile: target },
+                (Some(target), Some(area_of_effect)) => Targets::Area { center: target, radius: area_of_effect.radius },
+            };
+            if let Some(healing) = provides_healing_components.get(item_entity) {
+                // A cursed potion hurts instead of healing.
+                let effect_type = if is_cursed {
+                    EffectType::Damage { amount: healing.heal_amount }
+                } else {
+                    EffectType::Healing { amount: healing.heal_amount }
+                };
+                effect_queue.add(Some(entity), &source, effect_type, targets.clone());
+            }
+            if let Some(inflicts_damage) = inflicts_damage_components.get(item_entity) {
+                effect_queue.add(Some(entity), &source, EffectType::Damage { amount: inflicts_damage.damage }, targets.clone());
+            }
+            if let Some(confusion) = confusion_components.get(item_entity) {
+                effect_queue.add(Some(entity), &source, EffectType::Confusion { turns: confusion.turns }, targets.clone());
+            }
+            if charms_components.contains(item_entity) {
+                effect_queue.add(Some(entity), &source, EffectType::Charm, targets);
+            }
+
+            // Summoning Items
+            if let Some(summons) = summons_components.get(item_entity) {
+                if !is_cursed {
+                    effect_queue.add(Some(entity), &source, EffectType::Summon { name: summons.name.clone() }, Targets::User);
+                } else if is_user_visible {
+                    game_log.entries.push("Nothing answers the summons.".to_string());
+                }
             }
 
-            // Delete the Item if it is Consumable
+            // Charged Items use up a charge, and Consumable Items are deleted.
             if is_item_used {
                 let consumable = consumables.get(item_entity);
-                match consumable {
-                    None => {}
-                    Some(_) => {
+                if let Some(charges) = charges_components.get_mut(item_entity) {
+                    charges.current -= 1;
+                    continue;
+                }
+                match (consumable, stackables.get_mut(item_entity)) {
+                    (None, _) => {}
+                    (Some(_), Some(stackable)) if stackable.quantity > 1 => {
+                        // Only one of a stack is used up.
+                        stackable.quantity -= 1;
+                    }
+                    (Some(_), _) => {
                         entities.delete(item_entity).expect("Delete Entity Failed.")
                         // ^ Note: Since all of the placement information is attached to the potion itself,
                         // there's no need to chase around making sure it is removed from the appropriate backpack:
@@ -257,6 +527,63 @@ impl<'a> System<'a> for ItemUseSystem {
     }
 }
 
+/// Whether two items with the same name can share a stack. Cursed items only stack with other cursed items,
+/// and only once the player knows just as much about them.
+pub fn can_stack(cursed_components: &ReadStorage<Cursed>, item: Entity, other_item: Entity) -> bool {
+    let curse = |item: Entity| cursed_components.get(item).map(|cursed| cursed.discovered);
+    curse(item) == curse(other_item)
+}
+
+/// Finds a stack that "item" can join among the items whose "Holder" component passes "is_held", e.g. those in a backpack.
+pub fn matching_stack<Holder, H, S>(
+    entities: &Entities,
+    cursed_components: &ReadStorage<Cursed>,
+    holders: &Storage<Holder, H>,
+    names: &ReadStorage<Name>,
+    stackables: &Storage<Stackable, S>,
+    item: Entity,
+    is_held: impl Fn(&Holder) -> bool,
+) -> Option<Entity>
+where
+    Holder: Component,
+    H: Deref<Target = MaskedStorage<Holder>>,
+    S: Deref<Target = MaskedStorage<Stackable>>,
+{
+    if !stackables.contains(item) {
+        return None;
+    }
+    let item_name = &names.get(item).unwrap().name;
+    (entities, holders, names, stackables)
+        .join()
+        .find(|(stack, holder, stack_name, _stackable)| {
+            *stack != item
+                && is_held(holder)
+                && stack_name.name == *item_name
+                && can_stack(cursed_components, *stack, item)
+        })
+        .map(|(stack, _holder, _name, _stackable)| stack)
+}
+
+/// An item's name with how many there are, e.g. "Health Potion x3".
+pub fn stack_name(name: String, stackable: Option<&Stackable>) -> String {
+    match stackable {
+        Some(stackable) if stackable.quantity > 1 => format!("{} x{}", name, stackable.quantity),
+        _ => name,
+    }
+}
+
+/// Marks the item as known, telling the player what it was if they didn't know yet.
+/// Returns whether it was unknown until now.
+pub fn identify_item(item_identification: &mut ItemIdentification, game_log: &mut GameLog, name: &str) -> bool {
+    match item_identification.identify(name) {
+        None => false,
+        Some(unidentified_name) => {
+            game_log.entries.push(format!("You identify the {}: {}.", unidentified_name, name));
+            true
+        }
+    }
+}
+
 pub struct ItemDropSystem {}
 
 impl<'a> System<'a> for ItemDropSystem {
@@ -264,8 +591,10 @@ impl<'a> System<'a> for ItemDropSystem {
     type SystemData = (
         Entities<'a>,
         ReadExpect<'a, Entity>,
+        ReadExpect<'a, ItemIdentification>,
         WriteExpect<'a, GameLog>,
         ReadStorage<'a, Name>,
+        WriteStorage<'a, Cursed>,
         WriteStorage<'a, InBackpack>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, WantsToDropItem>,
@@ -274,13 +603,25 @@ impl<'a> System<'a> for ItemDropSystem {
     fn run(&mut self, data: Self::SystemData) {
         let (entities,
             player_entity,
+            item_identification,
             mut game_log,
             names,
+            mut cursed_components,
             mut in_backpacks,
             mut positions,
             mut wants_to_drop_items) = data;
 
         for (entity, wants_to_drop_item) in (&entities, &wants_to_drop_items).join() {
+            // Cursed Items won't leave their owner's hands, which is one way to find out about the curse.
+            if let Some(cursed) = cursed_components.get_mut(wants_to_drop_item.item) {
+                if entity == *player_entity {
+                    cursed.discovered = true;
+                    let item_name = item_identification.display_name(&names.get(wants_to_drop_item.item).unwrap().name);
+                    game_log.entries.push(format!("You can't let go of the {}. It's cursed!", item_name));
+                }
+                continue;
+            }
+
             let mut dropper_pos: Position = Position { x: 0, y: 0 };
             {
                 let dropped_pos = positions.get(entity).unwrap();
@@ -296,7 +637,7 @@ impl<'a> System<'a> for ItemDropSystem {
             in_backpacks.remove(wants_to_drop_item.item);
 
             if entity == *player_entity {
-                let item_name = &names.get(wants_to_drop_item.item).unwrap().name;
+                let item_name = item_identification.display_name(&names.get(wants_to_drop_item.item).unwrap().name);
                 game_log.entries.push(format!("You drop the {}.", item_name));
             }
         }
@@ -304,4 +645,187 @@ impl<'a> System<'a> for ItemDropSystem {
         // Clear all WantsToDropItem components for next tick.
         wants_to_drop_items.clear();
     }
-}
\ No newline at end of file
+}
+
+/// How far the player can throw things.
+pub const THROW_RANGE: i32 = 6;
+// Thrown items hit for this much damage per pound, and always for at least 1.
+const THROWN_DAMAGE_PER_POUND: f32 = 2.0;
+// How far from where it lands a shattered item's contents splash.
+const SHATTER_RADIUS: i32 = 1;
+
+// How hard an item hits whatever it's thrown at.
+fn thrown_damage(weight: Option<&Weight>) -> i32 {
+    let pounds = weight.map_or(0.0, |weight| weight.pounds);
+    i32::max(1, (pounds * THROWN_DAMAGE_PER_POUND).round() as i32)
+}
+
+pub struct ItemThrowSystem {}
+
+impl<'a> System<'a> for ItemThrowSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, ItemIdentification>,
+        WriteExpect<'a, ProjectileAnimation>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Confusion>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, ProvidesHealing>,
+        ReadStorage<'a, Renderer>,
+        ReadStorage<'a, Shatters>,
+        ReadStorage<'a, Weight>,
+        WriteStorage<'a, Cursed>,
+        WriteStorage<'a, InBackpack>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, WantsToThrowItem>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player_entity,
+            map,
+            mut effect_queue,
+            mut game_log,
+            mut item_identification,
+            mut projectile_animation,
+            combat_stats_components,
+            confusion_components,
+            names,
+            provides_healing_components,
+            renderers,
+            shatters_components,
+            weights,
+            mut cursed_components,
+            mut in_backpacks,
+            mut positions,
+            mut wants_to_throw_items,
+        ) = data;
+
+        for (entity, wants_to_throw_item) in (&entities, &wants_to_throw_items).join() {
+            let item_entity = wants_to_throw_item.item;
+            let item_name = item_identification.display_name(&names.get(item_entity).unwrap().name);
+
+            // Cursed Items won't leave their owner's hands, just like when dropping them.
+            if let Some(cursed) = cursed_components.get_mut(item_entity) {
+                if entity == *player_entity {
+                    cursed.discovered = true;
+                    game_log.entries.push(format!("You can't let go of the {}. It's cursed!", item_name));
+                }
+                continue;
+            }
+            let thrower_position = match positions.get(entity) {
+                None => continue,
+                Some(position) => Point::new(position.x, position.y),
+            };
+            let is_thrower_visible = entity == *player_entity
+                || map.visible_tiles[map.xy_idx(thrower_position.x, thrower_position.y)];
+            if entity == *player_entity {
+                game_log.entries.push(format!("You throw the {}.", item_name));
+            } else if is_thrower_visible {
+                game_log.entries.push(format!("{} throws a {}.", names.get(entity).unwrap().name, item_name));
+            }
+
+            // The item hits whoever is in the way; anything else blocking the way stops it short, like a wall.
+            let mut path = map.projectile_path(thrower_position, wants_to_throw_item.target);
+            let mut hit_targets: Vec<Entity> = Vec::new();
+            if let Some(last_point) = path.last() {
+                let index = map.xy_idx(last_point.x, last_point.y);
+                if map.blocked_tiles[index] {
+                    hit_targets = map.tile_contents[index]
+                        .iter()
+                        .filter(|target| combat_stats_components.contains(**target))
+                        .copied()
+                        .collect();
+                    if hit_targets.is_empty() {
+                        path.pop();
+                    }
+                }
+            }
+            let landing_point = *path.last().unwrap_or(&thrower_position);
+            projectile_animation.start(path, renderers.get(item_entity));
+
+            let is_landing_visible = map.visible_tiles[map.xy_idx(landing_point.x, landing_point.y)];
+            let is_shattered = shatters_components.contains(item_entity);
+            if is_shattered && is_landing_visible {
+                game_log.entries.push(format!("The {} shatters!", item_name));
+                identify_item(&mut item_identification, &mut game_log, &names.get(item_entity).unwrap().name);
+            }
+            let item_name = item_identification.display_name(&names.get(item_entity).unwrap().name);
+            let damage = thrown_damage(weights.get(item_entity));
+            for target in hit_targets {
+                effect_queue.add(Some(entity), &item_name, EffectType::Damage { amount: damage }, Targets::Entity { target });
+            }
+            in_backpacks.remove(item_entity);
+
+            if !is_shattered {
+                positions.insert(item_entity, Position { x: landing_point.x, y: landing_point.y })
+                    .expect("Unable to insert position");
+                continue;
+            }
+
+            // Shattering Items splash everyone around where they land, and are gone.
+            let splash = Targets::Area { center: landing_point, radius: SHATTER_RADIUS };
+            if let Some(healing) = provides_healing_components.get(item_entity) {
+                effect_queue.add(Some(entity), &item_name, EffectType::Healing { amount: healing.heal_amount }, splash.clone());
+            }
+            if let Some(confusion) = confusion_components.get(item_entity) {
+                effect_queue.add(Some(entity), &item_name, EffectType::Confusion { turns: confusion.turns }, splash);
+            }
+            entities.delete(item_entity).expect("Delete Entity Failed.");
+        }
+
+        // Clear all WantsToThrowItem components for next tick.
+        wants_to_throw_items.clear();
+    }
+}
+
+pub struct EncumbranceSystem {}
+
+impl<'a> System<'a> for EncumbranceSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, Stackable>,
+        ReadStorage<'a, Weight>,
+        WriteStorage<'a, Encumbered>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut game_log,
+            combat_stats_components,
+            in_backpacks,
+            stackables,
+            weights,
+            mut encumbered_components
+        ) = data;
+
+        let combat_stats = match combat_stats_components.get(*player_entity) {
+            None => return,
+            Some(combat_stats) => combat_stats,
+        };
+        let is_encumbered = carried_weight(*player_entity, &in_backpacks, &weights, &stackables) > carry_capacity(combat_stats);
+        match (is_encumbered, encumbered_components.contains(*player_entity)) {
+            (true, false) => {
+                encumbered_components.insert(*player_entity, Encumbered { monster_turns: 0 })
+                    .expect("Unable to insert Encumbered component.");
+                game_log.entries.push("You are carrying too much, and slow down.".to_string());
+            }
+            (false, true) => {
+                encumbered_components.remove(*player_entity);
+                game_log.entries.push("You are no longer encumbered.".to_string());
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{HashMap, HashSet};
+
+use rltk::RandomNumberGenerator;
+use serde::{Serialize, Deserialize};
+use super::raws::{RawMaster, RawUnidentified};
+
+// Scroll titles are a couple of words made from these.
+const SCROLL_SYLLABLES: [&str; 24] = [
+    "ZEL", "GO", "MER", "FOO", "BAR", "XOR", "KLAA", "TU", "NIK", "TO", "VER", "YUM",
+    "AN", "DO", "VA", "ELB", "IB", "HAK", "RUS", "PRA", "TY", "JUY", "THA", "RE",
+];
+
+const POTION_ADJECTIVES: [&str; 10] = [
+    "bubbling", "murky", "fizzy", "glowing", "swirling", "cloudy", "smoky", "oily", "sparkling", "viscous",
+];
+
+const POTION_COLORS: [&str; 10] = [
+    "red", "blue", "green", "yellow", "violet", "black", "white", "orange", "pink", "silver",
+];
+
+const WAND_MATERIALS: [&str; 10] = [
+    "oak", "ebony", "bone", "iron", "copper", "glass", "crystal", "ivory", "willow", "marble",
+];
+
+const WAND_STYLES: [&str; 6] = [
+    "carved", "twisted", "polished", "runed", "knotted", "slender",
+];
+
+// Gives up on finding an unused name after this many tries, and numbers the name instead.
+const MAX_NAME_ATTEMPTS: i32 = 100;
+
+/// Which kinds of item the player has identified, and what the others look like until then.
+/// The names are rolled once per game, so this is saved along with it.
+#[derive(Default, Serialize, Deserialize, Clone)]
+pub struct ItemIdentification {
+    identified: HashSet<String>,
+    unidentified_names: HashMap<String, String>,
+}
+
+impl ItemIdentification {
+    // Constructor
+    pub fn new(raw_master: &RawMaster, rng: &mut RandomNumberGenerator) -> ItemIdentification {
+        let mut item_identification = ItemIdentification::default();
+        item_identification.add_missing_names(raw_master, rng);
+        item_identification
+    }
+
+    /// Rolls names for unidentified items that don't have one yet, e.g. after the raws are reloaded.
+    pub fn add_missing_names(&mut self, raw_master: &RawMaster, rng: &mut RandomNumberGenerator) {
+        for (name, kind) in raw_master.unidentified_items() {
+            if self.unidentified_names.contains_key(name) { continue; }
+            let unidentified_name = self.roll_unused_name(kind, rng);
+            self.unidentified_names.insert(name.to_string(), unidentified_name);
+        }
+    }
+
+    /// What the player calls an item: its real name once identified, or the name it was given this game.
+    pub fn display_name(&self, name: &str) -> String {
+        match self.unidentified_names.get(name) {
+            Some(unidentified_name) if !self.identified.contains(name) => unidentified_name.clone(),
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn is_identified(&self, name: &str) -> bool {
+        !self.unidentified_names.contains_key(name) || self.identified.contains(name)
+    }
+
+    /// Marks every item with this name as known. Returns the name it went by until now,
+    /// or None if it was already known.
+    pub fn identify(&mut self, name: &str) -> Option<String> {
+        if self.is_identified(name) { return None; }
+        self.identified.insert(name.to_string());
+        self.unidentified_names.get(name).cloned()
+    }
+
+    fn roll_unused_name(&self, kind: RawUnidentified, rng: &mut RandomNumberGenerator) -> String {
+        let mut name = roll_name(kind, rng);
+        for _attempt in 0..MAX_NAME_ATTEMPTS {
+            if !self.unidentified_names.values().any(|taken_name| *taken_name == name) { return name; }
+            name = roll_name(kind, rng);
+        }
+        format!("{} #{}", name, self.unidentified_names.len() + 1)
+    }
+}
+
+fn roll_name(kind: RawUnidentified, rng: &mut RandomNumberGenerator) -> String {
+    match kind {
+        RawUnidentified::Scroll => {
+            let words: Vec<String> = (0..2)
+                .map(|_word| {
+                    let syllables = rng.range(1, 3);
+                    (0..syllables).map(|_syllable| *random_choice(&SCROLL_SYLLABLES, rng)).collect()
+                })
+                .collect();
+            format!("scroll of {}", words.join(" "))
+        }
+        RawUnidentified::Potion => {
+            format!("{} {} potion", random_choice(&POTION_ADJECTIVES, rng), random_choice(&POTION_COLORS, rng))
+        }
+        RawUnidentified::Wand => {
+            format!("{} {} wand", random_choice(&WAND_STYLES, rng), random_choice(&WAND_MATERIALS, rng))
+        }
+    }
+}
+
+fn random_choice<'a>(choices: &'a [&'a str], rng: &mut RandomNumberGenerator) -> &'a &'a str {
+    &choices[rng.range(0, choices.len() as i32) as usize]
+}
@@ -1,37 +1,58 @@
 extern crate serde;
 
-use rltk::{Rltk, GameState, Point};
+use rltk::{Rltk, GameState, Point, RGB};
 use specs::prelude::*;
 use specs::saveload::{SimpleMarker, SimpleMarkerAllocator}; // To use the Marker functionality.
 
+mod bark_system;
+mod benchmark;
 mod components;
+mod container;
 mod damage_system;
+mod effects_system;
+mod faction_table;
 mod game_log;
 mod gui;
+mod hunger_system;
 mod inventory_system;
+mod item_identification;
+mod mana_system;
 mod map;
 mod map_indexing_system;
 mod melee_combat_system;
 mod monster_ai_system;
+mod pathing_map_system;
 mod player;
 mod random_table;
+mod ranged_combat_system;
+mod raws;
 mod rect;
 mod save_load_system;
+mod shop;
+mod spawn_analysis;
 mod spawner;
 mod visibility_system;
 
 pub use components::*;
+use bark_system::BarkSystem;
 use damage_system::DamageSystem;
+use effects_system::{EffectQueue, EffectsSystem};
+pub use faction_table::*;
 pub use game_log::GameLog;
-use inventory_system::{ItemCollectionSystem, ItemDropSystem, ItemUseSystem};
+use hunger_system::HungerSystem;
+use inventory_system::{EncumbranceSystem, ItemCollectionSystem, ItemDropSystem, ItemThrowSystem, ItemUseSystem, THROW_RANGE};
+pub use item_identification::ItemIdentification;
+use mana_system::ManaRegenerationSystem;
 pub use map::*;
 use map_indexing_system::MapIndexingSystem;
 use melee_combat_system::MeleeCombatSystem;
 use monster_ai_system::MonsterAI;
+pub use pathing_map_system::{PathingMapSystem, PathingMaps};
 use player::*;
 pub use random_table::*;
+use ranged_combat_system::{ProjectileAnimation, RangedCombatSystem};
 pub use rect::Rect;
-pub use visibility_system::VisibilitySystem;
+pub use visibility_system::{DetectionSystem, VisibilitySystem};
 
 pub struct State {
     pub ecs: World,
@@ -40,6 +61,9 @@ pub struct State {
 #[derive(PartialEq, Copy, Clone)]
 pub enum RunState {
     AwaitingInput,
+    MagicMapReveal {
+        row: i32,
+    },
     MainMenu {
         menu_selection: gui::MainMenuSelection
     },
@@ -48,41 +72,115 @@ pub enum RunState {
     PreRun,
     PlayerTurn,
     SaveGame,
+    ShowCompanionOrders,
+    ShowCompanionTargeting,
+    ShowContainer {
+        container: Entity,
+        side: gui::ContainerSide,
+    },
     ShowDropItem,
+    ShowFireTargeting {
+        weapon: Entity,
+        target: Entity,
+    },
     ShowInventory,
+    ShowProjectile {
+        step: i32,
+    },
+    ShowShop {
+        shopkeeper: Entity,
+        mode: gui::ShopMode,
+    },
+    ShowSpells,
     ShowTargeting {
         range: i32,
         item: Entity,
     },
+    ShowThrowItem,
+    ShowThrowTargeting {
+        item: Entity,
+    },
 }
 
 impl State {
     fn run_systems(&mut self) {
         let mut visibility_system = VisibilitySystem {};
         visibility_system.run_now(&self.ecs);
+        let mut detection_system = DetectionSystem {};
+        detection_system.run_now(&self.ecs);
+        let mut mana_regeneration_system = ManaRegenerationSystem {};
+        mana_regeneration_system.run_now(&self.ecs);
+        let mut hunger_system = HungerSystem {};
+        hunger_system.run_now(&self.ecs);
+        let mut pathing_map_system = PathingMapSystem {};
+        pathing_map_system.run_now(&self.ecs);
         let mut monster_ai_system = MonsterAI {};
         monster_ai_system.run_now(&self.ecs);
+        let mut bark_system = BarkSystem {};
+        bark_system.run_now(&self.ecs);
         let mut map_indexing_system = MapIndexingSystem {};
         map_indexing_system.run_now(&self.ecs);
         let mut melee_combat_system = MeleeCombatSystem {};
         melee_combat_system.run_now(&self.ecs);
-        let mut damage_system = DamageSystem {};
-        damage_system.run_now(&self.ecs);
+        let mut ranged_combat_system = RangedCombatSystem {};
+        ranged_combat_system.run_now(&self.ecs);
         let mut item_collection_system = ItemCollectionSystem {};
         item_collection_system.run_now(&self.ecs);
         let mut item_use_system = ItemUseSystem {};
         item_use_system.run_now(&self.ecs);
         let mut drop_items = ItemDropSystem {};
         drop_items.run_now(&self.ecs);
+        let mut item_throw_system = ItemThrowSystem {};
+        item_throw_system.run_now(&self.ecs);
+        // Everything above queues its effects, so damage from any of them lands this turn.
+        let mut effects_system = EffectsSystem {};
+        effects_system.run_now(&self.ecs);
+        let mut damage_system = DamageSystem {};
+        damage_system.run_now(&self.ecs);
+        let mut encumbrance_system = EncumbranceSystem {};
+        encumbrance_system.run_now(&self.ecs);
+        spawner::summon_companions(&mut self.ecs);
+        spawner::learn_spells(&mut self.ecs);
         self.ecs.maintain(); // Tells Specs to apply any changes that are queued up.
     }
 
+    // Items and spells with a range need a target picking first; anything else is used straight away.
+    fn use_or_target(&mut self, item_entity: Entity) -> RunState {
+        // Check if we have Ranged component
+        let ranged_components = self.ecs.read_storage::<Ranged>();
+        let possible_ranged_item = ranged_components.get(item_entity);
+        if let Some(ranged_item) = possible_ranged_item {
+            return RunState::ShowTargeting {
+                range: ranged_item.range,
+                item: item_entity,
+            };
+        }
+
+        // It must be a non-ranged item, i.e. Health Potion
+        let player_entity = self.ecs.fetch::<Entity>();
+        let mut wants_to_use_item_components = self.ecs.write_storage::<WantsToUseItem>();
+        wants_to_use_item_components.insert(
+            *player_entity,
+            WantsToUseItem {
+                item: item_entity,
+                target: None,
+            },
+        ).expect("Unable to insert WantsToUseItem component.");
+        RunState::PlayerTurn
+    }
+
     fn entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
         let entities = self.ecs.entities();
         let player = self.ecs.read_storage::<Player>();
+        let abilities = self.ecs.read_storage::<Ability>();
         let backpack_components = self.ecs.read_storage::<InBackpack>();
+        let companions = self.ecs.read_storage::<Companion>();
+        let known_spells = self.ecs.read_storage::<KnownSpell>();
         let player_entity = self.ecs.fetch::<Entity>();
 
+        // Companions come along, so anything belonging to the player or a companion is kept.
+        let is_kept = |owner: Entity| owner == *player_entity || companions.get(owner).is_some();
+
         let mut entities_to_delete: Vec<Entity> = Vec::new();
         for entity in entities.join() {
             let mut should_delete = true;
@@ -93,10 +191,29 @@ impl State {
                 should_delete = false;
             }
 
-            // Don't delete the players equipment
+            // Don't delete the player's companions
+            if companions.get(entity).is_some() {
+                should_delete = false;
+            }
+
+            // Don't delete the players (or their companions) equipment
             let backpack = backpack_components.get(entity);
             if let Some(backpack) = backpack {
-                if backpack.owner == *player_entity {
+                if is_kept(backpack.owner) {
+                    should_delete = false;
+                }
+            }
+
+            // Don't delete the companions abilities
+            if let Some(ability) = abilities.get(entity) {
+                if is_kept(ability.owner) {
+                    should_delete = false;
+                }
+            }
+
+            // Don't delete the players spells
+            if let Some(known_spell) = known_spells.get(entity) {
+                if is_kept(known_spell.owner) {
                     should_delete = false;
                 }
             }
@@ -108,7 +225,7 @@ impl State {
     }
 
     fn goto_next_level(&mut self) {
-        // Delete the entities that aren't the player or his/her equipment.
+        // Delete the entities that aren't the player, his/her companions or their equipment.
         let entities_to_delete = self.entities_to_remove_on_level_change();
         for entity in entities_to_delete {
             self.ecs.delete_entity(entity).expect("Unable to delete Entity");
@@ -125,9 +242,7 @@ impl State {
         }
 
         // Create Monsters
-        for room in world_map.rooms.iter().skip(1) {
-            spawner::fill_room(&mut self.ecs, room, current_depth + 1);
-        };
+        spawner::populate_level(&mut self.ecs, &world_map);
 
         // Place the player and update resources
         let (player_new_x, player_new_y) = world_map.rooms[0].center();
@@ -141,6 +256,25 @@ impl State {
             player_position_component.y = player_new_y;
         }
 
+        // Companions arrive next to the player.
+        {
+            let entities = self.ecs.entities();
+            let companions = self.ecs.read_storage::<Companion>();
+            let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
+            let mut map = self.ecs.write_resource::<Map>();
+            for (companion_entity, _companion, companion_position) in (&entities, &companions, &mut position_components).join() {
+                if let Some((x, y)) = spawner::find_free_tile_near(&map, Point::new(player_new_x, player_new_y)) {
+                    companion_position.x = x;
+                    companion_position.y = y;
+                    let index = map.xy_idx(x, y);
+                    map.blocked_tiles[index] = true;
+                }
+                if let Some(companion_viewshed) = viewshed_components.get_mut(companion_entity) {
+                    companion_viewshed.dirty = true;
+                }
+            }
+        }
+
         // Mark the players visibility as dirty.
         let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
         let player_viewshed_component = viewshed_components.get_mut(*player_entity);
@@ -164,6 +298,9 @@ impl State {
 
 impl GameState for State {
     fn tick(&mut self, context: &mut Rltk) {
+        // Pick up any balance changes made to the raws while playing.
+        raws::poll_raws_file(&mut self.ecs);
+
         // Get RunState resource
         let current_run_state = *self.ecs.fetch::<RunState>();
         let mut new_run_state = current_run_state;
@@ -182,16 +319,20 @@ impl GameState for State {
                     // Render Entities: Here we're calling into the ECS to perform the Rendering
                     let positions = self.ecs.read_storage::<Position>();
                     let renderers = self.ecs.read_storage::<Renderer>();
-                    let mut render_data = (&positions, &renderers)
+                    let items = self.ecs.read_storage::<Item>();
+                    let monsters = self.ecs.read_storage::<Monster>();
+                    // Detection lets the player see those outside their view too.
+                    let player_entity = self.ecs.fetch::<Entity>();
+                    let is_detecting_items = self.ecs.read_storage::<DetectItems>().contains(*player_entity);
+                    let is_detecting_monsters = self.ecs.read_storage::<DetectMonsters>().contains(*player_entity);
+                    let mut render_data = (&positions, &renderers, items.maybe(), monsters.maybe())
                         .join()
                         .collect::<Vec<_>>();
-                    render_data.sort_by(
-                        |&a, &b|
-                            b.1.render_order.cmp(&a.1.render_order)
-                    );
-                    for (position, renderer) in render_data.iter() {
+                    render_data.sort_by_key(|&(_position, renderer, _item, _monster)| -renderer.render_order);
+                    for (position, renderer, item, monster) in render_data.iter() {
                         let index = map.xy_idx(position.x, position.y);
-                        if map.visible_tiles[index] {
+                        let is_detected = (is_detecting_items && item.is_some()) || (is_detecting_monsters && monster.is_some());
+                        if map.visible_tiles[index] || is_detected {
                             context.set(position.x, position.y, renderer.fg, renderer.bg, renderer.glyph)
                         }
                     }
@@ -216,7 +357,40 @@ impl GameState for State {
                 // Here the ECS is calling out to our functions and components.
                 self.run_systems(); // Within run_systems(...)
                 self.ecs.maintain();
-                new_run_state = RunState::MonsterTurn;
+                // A magic map or a projectile in flight interrupts the turn while it is drawn.
+                new_run_state = match *self.ecs.fetch::<RunState>() {
+                    RunState::MagicMapReveal { .. } => RunState::MagicMapReveal { row: 0 },
+                    _ if !self.ecs.fetch::<ProjectileAnimation>().path.is_empty() => RunState::ShowProjectile { step: 0 },
+                    _ => RunState::MonsterTurn,
+                };
+            }
+            RunState::ShowProjectile { step } => {
+                let mut projectile_animation = self.ecs.fetch_mut::<ProjectileAnimation>();
+                let map = self.ecs.fetch::<Map>();
+                match projectile_animation.path.get(step as usize) {
+                    None => {
+                        projectile_animation.path.clear();
+                        new_run_state = RunState::MonsterTurn;
+                    }
+                    Some(point) => {
+                        if map.visible_tiles[map.xy_idx(point.x, point.y)] {
+                            context.set(point.x, point.y, projectile_animation.fg, RGB::named(rltk::BLACK), projectile_animation.glyph);
+                        }
+                        new_run_state = RunState::ShowProjectile { step: step + 1 };
+                    }
+                }
+            }
+            RunState::MagicMapReveal { row } => {
+                let mut map = self.ecs.fetch_mut::<Map>();
+                for x in 0..map.width {
+                    let index = map.xy_idx(x, row);
+                    map.revealed_tiles[index] = true;
+                }
+                new_run_state = if row == map.height - 1 {
+                    RunState::MonsterTurn
+                } else {
+                    RunState::MagicMapReveal { row: row + 1 }
+                };
             }
             RunState::MainMenu { .. } => {
                 let main_menu_result = gui::main_menu(self, context);
@@ -244,7 +418,11 @@ impl GameState for State {
             RunState::MonsterTurn => {
                 self.run_systems();
                 self.ecs.maintain();
-                new_run_state = RunState::AwaitingInput;
+                new_run_state = if is_player_turn_lost(&mut self.ecs) {
+                    RunState::MonsterTurn
+                } else {
+                    RunState::AwaitingInput
+                };
             }
             RunState::NextLevel => {
                 self.goto_next_level();
@@ -265,45 +443,161 @@ impl GameState for State {
                     gui::ItemMenuResult::NoResponse => {} // Do Nothing
                     gui::ItemMenuResult::Selected => {
                         // We're unwrapping here because if we have ItemMenuResult::Selected we know there must be an item from show_inventory(...)
-                        let item_entity = result.1.unwrap();
-
-                        // Check if we have Ranged component
-                        let ranged_components = self.ecs.read_storage::<Ranged>();
-                        let possible_ranged_item = ranged_components.get(item_entity);
-                        if let Some(ranged_item) = possible_ranged_item {
-                            new_run_state = RunState::ShowTargeting {
-                                range: ranged_item.range,
-                                item: item_entity,
-                            }
+                        new_run_state = self.use_or_target(result.1.unwrap());
+                    }
+                }
+            }
+            RunState::ShowSpells => {
+                let result = gui::spell_menu(self, context);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        let spell_entity = result.1.unwrap();
+                        let mana_cost = self.ecs.read_storage::<KnownSpell>().get(spell_entity).unwrap().mana_cost;
+                        let current_mana = self.ecs.read_storage::<Mana>().get(*self.ecs.fetch::<Entity>()).map_or(0, |mana| mana.current);
+                        if mana_cost > current_mana {
+                            // Don't make the player pick a target for a spell they can't cast.
+                            let spell_name = self.ecs.read_storage::<Name>().get(spell_entity).unwrap().name.clone();
+                            self.ecs.write_resource::<GameLog>().entries
+                                .push(format!("You don't have enough mana to cast {}.", spell_name));
+                            new_run_state = RunState::AwaitingInput;
                         } else {
-                            // It must be a non-ranged item, i.e. Health Potion (since that's all we have right now)
-                            let player_entity = self.ecs.fetch::<Entity>();
-                            let mut wants_to_use_item_components = self.ecs.write_storage::<WantsToUseItem>();
-                            wants_to_use_item_components.insert(
-                                *player_entity,
-                                WantsToUseItem {
-                                    item: item_entity,
-                                    target: None,
-                                },
-                            ).expect("Unable to insert WantsToUseItem component.");
-                            new_run_state = RunState::PlayerTurn;
+                            new_run_state = self.use_or_target(spell_entity);
                         }
                     }
                 }
             }
+            RunState::ShowShop { shopkeeper, mode } => {
+                let result = gui::shop_menu(self, context, shopkeeper, mode);
+                match result.0 {
+                    gui::ShopMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ShopMenuResult::NoResponse => {}
+                    gui::ShopMenuResult::SwitchMode => {
+                        let mode = if mode == gui::ShopMode::Buy { gui::ShopMode::Sell } else { gui::ShopMode::Buy };
+                        new_run_state = RunState::ShowShop { shopkeeper, mode };
+                    }
+                    // Trading doesn't take a turn, so the menu stays open for the next deal.
+                    gui::ShopMenuResult::Selected => match mode {
+                        gui::ShopMode::Buy => shop::buy_item(&mut self.ecs, result.1.unwrap()),
+                        gui::ShopMode::Sell => shop::sell_item(&mut self.ecs, shopkeeper, result.1.unwrap()),
+                    },
+                }
+            }
+            RunState::ShowContainer { container, side } => {
+                let result = gui::container_menu(self, context, container, side);
+                match result.0 {
+                    gui::ContainerMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ContainerMenuResult::NoResponse => {}
+                    gui::ContainerMenuResult::SwitchSide => {
+                        let side = if side == gui::ContainerSide::Container { gui::ContainerSide::Backpack } else { gui::ContainerSide::Container };
+                        new_run_state = RunState::ShowContainer { container, side };
+                    }
+                    // Moving things in and out doesn't take a turn, so the menu stays open.
+                    gui::ContainerMenuResult::Selected => match side {
+                        gui::ContainerSide::Container => container::take_item(&mut self.ecs, container, result.1.unwrap()),
+                        gui::ContainerSide::Backpack => container::put_item(&mut self.ecs, container, result.1.unwrap()),
+                    },
+                }
+            }
+            RunState::ShowCompanionOrders => {
+                let result = gui::companion_orders_menu(context);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        match result.1.unwrap() {
+                            gui::CompanionOrderSelection::Follow => {
+                                order_companions(&mut self.ecs, CompanionOrder::Follow);
+                                new_run_state = RunState::AwaitingInput;
+                            }
+                            gui::CompanionOrderSelection::Stay => {
+                                order_companions(&mut self.ecs, CompanionOrder::Stay);
+                                new_run_state = RunState::AwaitingInput;
+                            }
+                            gui::CompanionOrderSelection::Attack => new_run_state = RunState::ShowCompanionTargeting,
+                        }
+                    }
+                }
+            }
+            RunState::ShowCompanionTargeting => {
+                let range = {
+                    let viewsheds = self.ecs.read_storage::<Viewshed>();
+                    let player_entity = self.ecs.fetch::<Entity>();
+                    viewsheds.get(*player_entity).map_or(0, |viewshed| viewshed.range)
+                };
+                let result = gui::ranged_target(self, context, range);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        order_companions_to_attack(&mut self.ecs, result.1.unwrap());
+                        new_run_state = RunState::AwaitingInput;
+                    }
+                }
+            }
             RunState::ShowDropItem => {
                 let result = gui::drop_item_menu(self, context);
                 match result.0 {
                     gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
                     gui::ItemMenuResult::NoResponse => {}
                     gui::ItemMenuResult::Selected => {
-                        let item_entity = result.1.unwrap();
+                        // Only one item of a stack is dropped.
+                        let item_entity = spawner::split_stack(&mut self.ecs, result.1.unwrap());
                         let mut intent = self.ecs.write_storage::<WantsToDropItem>();
                         intent.insert(*self.ecs.fetch::<Entity>(), WantsToDropItem { item: item_entity }).expect("Unable to insert intent");
                         new_run_state = RunState::PlayerTurn;
                     }
                 }
             }
+            RunState::ShowFireTargeting { weapon, target } => {
+                let range = self.ecs.read_storage::<RangedWeapon>().get(weapon).map_or(0, |ranged_weapon| ranged_weapon.range);
+                match gui::fire_target(self, context, range, target) {
+                    gui::FireTargetResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::FireTargetResult::NoSelection { target } => {
+                        new_run_state = RunState::ShowFireTargeting { weapon, target };
+                    }
+                    gui::FireTargetResult::Selected { target } => {
+                        let player_entity = *self.ecs.fetch::<Entity>();
+                        new_run_state = RunState::AwaitingInput;
+                        if let Some(ammo) = ranged_combat_system::find_ammo(&self.ecs, player_entity, weapon) {
+                            // Only one of a stack of ammunition is shot.
+                            let ammo = spawner::split_stack(&mut self.ecs, ammo);
+                            let mut intent = self.ecs.write_storage::<WantsToShoot>();
+                            intent.insert(player_entity, WantsToShoot { weapon, ammo, target })
+                                .expect("Unable to insert intent");
+                            new_run_state = RunState::PlayerTurn;
+                        }
+                    }
+                }
+            }
+            RunState::ShowThrowItem => {
+                let result = gui::throw_item_menu(self, context);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        new_run_state = RunState::ShowThrowTargeting { item: result.1.unwrap() };
+                    }
+                }
+            }
+            RunState::ShowThrowTargeting { item } => {
+                let result = gui::ranged_target(self, context, THROW_RANGE);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        // Only one item of a stack is thrown.
+                        let item_entity = spawner::split_stack(&mut self.ecs, item);
+                        let mut intent = self.ecs.write_storage::<WantsToThrowItem>();
+                        intent.insert(
+                            *self.ecs.fetch::<Entity>(),
+                            WantsToThrowItem { item: item_entity, target: result.1.unwrap() },
+                        ).expect("Unable to insert intent");
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                }
+            }
             RunState::ShowTargeting { range, item } => {
                 let result = gui::ranged_target(self, context, range);
                 match result.0 {
@@ -338,6 +632,21 @@ impl GameState for State {
 
 fn main() -> rltk::BError {
     use rltk::RltkBuilder;
+
+    // "cargo run --release -- --benchmark" times the monster turn instead of starting the game.
+    if std::env::args().any(|argument| argument == "--benchmark") {
+        benchmark::run_monster_turn_benchmark();
+        return Ok(());
+    }
+
+    // "cargo run -- --spawn-analysis 10" prints what spawns at depths 1 to 10 instead of starting the game.
+    let arguments: Vec<String> = std::env::args().collect();
+    if let Some(position) = arguments.iter().position(|argument| argument == "--spawn-analysis") {
+        let max_depth = arguments.get(position + 1).and_then(|depth| depth.parse().ok()).unwrap_or(10);
+        spawn_analysis::run_spawn_analysis(max_depth);
+        return Ok(());
+    }
+
     let mut context = RltkBuilder::simple80x50()
         .with_title("Roguelike")
         .build()?;
@@ -347,29 +656,71 @@ fn main() -> rltk::BError {
     };
 
     // Register Components with the ECS.
+    game_state.ecs.register::<Ability>();
     game_state.ecs.register::<AreaOfEffect>();
+    game_state.ecs.register::<Barks>();
     game_state.ecs.register::<BlocksTile>();
+    game_state.ecs.register::<Charges>();
+    game_state.ecs.register::<Charms>();
     game_state.ecs.register::<CombatStats>();
+    game_state.ecs.register::<Companion>();
     game_state.ecs.register::<Confusion>();
     game_state.ecs.register::<Consumable>();
+    game_state.ecs.register::<Container>();
+    game_state.ecs.register::<Cursed>();
+    game_state.ecs.register::<DetectItems>();
+    game_state.ecs.register::<DetectMonsters>();
+    game_state.ecs.register::<Encumbered>();
+    game_state.ecs.register::<Faction>();
+    game_state.ecs.register::<Gold>();
+    game_state.ecs.register::<HungerClock>();
+    game_state.ecs.register::<Identifies>();
     game_state.ecs.register::<InBackpack>();
+    game_state.ecs.register::<InContainer>();
     game_state.ecs.register::<InflictsDamage>();
     game_state.ecs.register::<Item>();
+    game_state.ecs.register::<KeepsDistance>();
+    game_state.ecs.register::<KnownSpell>();
+    game_state.ecs.register::<LeavesCorpse>();
+    game_state.ecs.register::<Locked>();
+    game_state.ecs.register::<LootTable>();
+    game_state.ecs.register::<MagicMapper>();
+    game_state.ecs.register::<Mana>();
     game_state.ecs.register::<Monster>();
     game_state.ecs.register::<Name>();
+    game_state.ecs.register::<Peaceful>();
     game_state.ecs.register::<Player>();
     game_state.ecs.register::<Position>();
+    game_state.ecs.register::<ProvidesFood>();
     game_state.ecs.register::<ProvidesHealing>();
+    game_state.ecs.register::<Purse>();
     game_state.ecs.register::<Ranged>();
+    game_state.ecs.register::<RangedWeapon>();
+    game_state.ecs.register::<Recharges>();
+    game_state.ecs.register::<RemovesCurse>();
     game_state.ecs.register::<Renderer>();
     game_state.ecs.register::<SerializationHelper>();
+    game_state.ecs.register::<Shatters>();
+    game_state.ecs.register::<Stackable>();
     game_state.ecs.register::<SufferDamage>();
+    game_state.ecs.register::<Summons>();
+    game_state.ecs.register::<TeachesSpell>();
+    game_state.ecs.register::<Teleports>();
+    game_state.ecs.register::<UsesItems>();
+    game_state.ecs.register::<Value>();
+    game_state.ecs.register::<Vendor>();
     game_state.ecs.register::<Viewshed>();
+    game_state.ecs.register::<Wanders>();
     game_state.ecs.register::<WantsToDrinkPotion>();
     game_state.ecs.register::<WantsToDropItem>();
+    game_state.ecs.register::<WantsToLearnSpell>();
     game_state.ecs.register::<WantsToMelee>();
     game_state.ecs.register::<WantsToPickupItem>();
+    game_state.ecs.register::<WantsToShoot>();
+    game_state.ecs.register::<WantsToSummon>();
+    game_state.ecs.register::<WantsToThrowItem>();
     game_state.ecs.register::<WantsToUseItem>();
+    game_state.ecs.register::<Weight>();
 
     // Register Markers with the ECS.
     game_state.ecs.register::<SimpleMarker<SerializeMe>>();
@@ -377,8 +728,8 @@ fn main() -> rltk::BError {
     // Add an entry to the ECS resources, to determine the next identity:
     game_state.ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
 
-    // Generate the Map
-    let map = Map::new_map_rooms_and_corridors(1);
+    // Generate the Map. The game starts in the town above the dungeon.
+    let map = Map::new_town();
 
     // Get Player Start Position
     let (player_x, player_y) = map.rooms[0].center();
@@ -389,16 +740,30 @@ fn main() -> rltk::BError {
     // Insert the RNG as an ECS Resource
     game_state.ecs.insert(rltk::RandomNumberGenerator::new());
 
-    // Create Monsters
-    for room in map.rooms.iter().skip(1) {
-        spawner::fill_room(&mut game_state.ecs, room, 1);
-    };
+    // Load the monster, item and spawn table definitions, and watch them for changes.
+    game_state.ecs.insert(raws::load_raws());
+    game_state.ecs.insert(raws::RawsWatcher::new());
+    spawner::learn_starting_spells(&mut game_state.ecs, player_entity);
+
+    // Roll this game's names for unidentified scrolls and potions.
+    let item_identification = ItemIdentification::new(
+        &game_state.ecs.fetch::<raws::RawMaster>(),
+        &mut game_state.ecs.write_resource::<rltk::RandomNumberGenerator>(),
+    );
+    game_state.ecs.insert(item_identification);
+
+    // Create the Townsfolk. The Map goes in first, since spawning can depend on it.
+    game_state.ecs.insert(map.clone());
+    spawner::populate_town(&mut game_state.ecs, &map);
 
     // Add resources to the ECS. (Kinda like global variables?)
     game_state.ecs.insert(GameLog {
-        entries: vec!["You find yourself in a dark room with no recollection of who you are.".to_string()]
+        entries: vec!["You arrive in a quiet town. The dungeon entrance lies to the east.".to_string()]
     });
-    game_state.ecs.insert(map);
+    game_state.ecs.insert(EffectQueue::new());
+    game_state.ecs.insert(PathingMaps::new(map.width, map.height));
+    game_state.ecs.insert(ProjectileAnimation::new());
+    game_state.ecs.insert(spawner::faction_table());
     game_state.ecs.insert(player_entity);
     game_state.ecs.insert(Point::new(player_x, player_y));
     game_state.ecs.insert(RunState::MainMenu {
@@ -0,0 +1,32 @@
+use specs::prelude::*;
+use super::{Mana, RunState};
+
+// How many of the player's turns it takes to regain a point of mana.
+const MANA_REGENERATION_TURNS: i32 = 4;
+
+pub struct ManaRegenerationSystem {}
+
+impl<'a> System<'a> for ManaRegenerationSystem {
+    type SystemData = (
+        ReadExpect<'a, RunState>,
+        WriteStorage<'a, Mana>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (run_state, mut manas) = data;
+        // Only the player's turns count, so opening a menu doesn't regenerate anything.
+        if *run_state != RunState::PlayerTurn { return; }
+
+        for mana in (&mut manas).join() {
+            if mana.current >= mana.max {
+                mana.regeneration_turns = 0;
+                continue;
+            }
+            mana.regeneration_turns += 1;
+            if mana.regeneration_turns >= MANA_REGENERATION_TURNS {
+                mana.current += 1;
+                mana.regeneration_turns = 0;
+            }
+        }
+    }
+}
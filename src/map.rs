@@ -10,6 +10,7 @@ pub const MAP_COUNT: usize = MAP_WIDTH * MAP_HEIGHT;
 
 #[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum TileType {
+    Door,
     Floor,
     Downstairs,
     Wall,
@@ -39,29 +40,12 @@ impl Algorithm2D for Map {
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx] == TileType::Wall
+        // Doors can be walked through, but hide what's inside until you're standing in them.
+        self.tiles[idx] == TileType::Wall || self.tiles[idx] == TileType::Door
     }
 
     fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
-        let mut exits = SmallVec::new();
-        let x = idx as i32 % self.width;
-        let y = idx as i32 / self.width;
-        let w = self.width as usize;
-
-        // Cardinal Directions
-        if self.is_exit_valid(x - 1, y) { exits.push((idx - 1, 1.0)); }
-        if self.is_exit_valid(x + 1, y) { exits.push((idx + 1, 1.0)); }
-        if self.is_exit_valid(x, y - 1) { exits.push((idx - w, 1.0)); }
-        if self.is_exit_valid(x, y + 1) { exits.push((idx + w, 1.0)); }
-
-        // Diagonal Directions
-        if self.is_exit_valid(x - 1, y - 1) { exits.push(((idx - w) - 1, 1.45)); }
-        if self.is_exit_valid(x + 1, y - 1) { exits.push(((idx - w) + 1, 1.45)); }
-        if self.is_exit_valid(x - 1, y + 1) { exits.push(((idx + w) - 1, 1.45)); }
-        if self.is_exit_valid(x + 1, y + 1) { exits.push(((idx + w) + 1, 1.45)); }
-        // ^ Note to self on the above, I believe the tuple above is (index, distance)
-
-        exits
+        self.exits_where(idx, Map::is_exit_valid)
     }
 
     fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
@@ -86,6 +70,77 @@ impl Map {
         !self.blocked_tiles[index]
     }
 
+    // Like "is_exit_valid(...)", but only walls count; creatures standing on the tile are ignored.
+    pub fn is_terrain_walkable(&self, x: i32, y: i32) -> bool {
+        if x < 1 || x > self.width - 1 || y < 1 || y > self.height - 1 { return false; };
+        let index = self.xy_idx(x, y);
+        self.tiles[index] != TileType::Wall
+    }
+
+    /// Exits from a tile that only take walls into account.
+    /// Used to build flow maps that shouldn't be disrupted by monsters standing in a corridor.
+    pub fn get_terrain_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        self.exits_where(idx, Map::is_terrain_walkable)
+    }
+
+    // Shared by "get_available_exits(...)" and "get_terrain_exits(...)".
+    fn exits_where(&self, idx: usize, is_valid: fn(&Map, i32, i32) -> bool) -> SmallVec<[(usize, f32); 10]> {
+        let mut exits = SmallVec::new();
+        let x = idx as i32 % self.width;
+        let y = idx as i32 / self.width;
+        let w = self.width as usize;
+
+        // Cardinal Directions
+        if is_valid(self, x - 1, y) { exits.push((idx - 1, 1.0)); }
+        if is_valid(self, x + 1, y) { exits.push((idx + 1, 1.0)); }
+        if is_valid(self, x, y - 1) { exits.push((idx - w, 1.0)); }
+        if is_valid(self, x, y + 1) { exits.push((idx + w, 1.0)); }
+
+        // Diagonal Directions
+        if is_valid(self, x - 1, y - 1) { exits.push(((idx - w) - 1, 1.45)); }
+        if is_valid(self, x + 1, y - 1) { exits.push(((idx - w) + 1, 1.45)); }
+        if is_valid(self, x - 1, y + 1) { exits.push(((idx + w) - 1, 1.45)); }
+        if is_valid(self, x + 1, y + 1) { exits.push(((idx + w) + 1, 1.45)); }
+        // ^ Note to self on the above, I believe the tuple above is (index, distance)
+
+        exits
+    }
+
+    /// True if nothing opaque lies on the straight line between the two points.
+    /// The start and end tiles themselves are not checked.
+    pub fn has_line_of_fire(&self, start: Point, end: Point) -> bool {
+        let line = rltk::line2d(rltk::LineAlg::Bresenham, start, end);
+        for point in line.iter() {
+            if *point == start || *point == end { continue; }
+            if self.is_opaque(self.xy_idx(point.x, point.y)) { return false; }
+        }
+        true
+    }
+
+    /// The tiles something flying from "start" towards "end" passes through. It stops short of the first wall,
+    /// and on the first blocked tile, where whatever is standing there is in the way.
+    pub fn projectile_path(&self, start: Point, end: Point) -> Vec<Point> {
+        let mut path = Vec::new();
+        for point in rltk::line2d(rltk::LineAlg::Bresenham, start, end) {
+            if point == start { continue; }
+            let index = self.xy_idx(point.x, point.y);
+            if self.tiles[index] == TileType::Wall { break; }
+            path.push(point);
+            if self.blocked_tiles[index] { break; }
+        }
+        path
+    }
+
+    /// A random floor tile that nothing is standing on, or None if there isn't one.
+    pub fn random_free_floor_tile(&self, rng: &mut RandomNumberGenerator) -> Option<Point> {
+        let free_tiles: Vec<usize> = (0..self.tiles.len())
+            .filter(|index| self.tiles[*index] == TileType::Floor && !self.blocked_tiles[*index])
+            .collect();
+        if free_tiles.is_empty() { return None; }
+        let index = free_tiles[rng.range(0, free_tiles.len() as i32) as usize] as i32;
+        Some(Point::new(index % self.width, index / self.width))
+    }
+
     pub fn calculate_blocked_tiles(&mut self) {
         for (i, tile) in self.tiles.iter().enumerate() {
             self.blocked_tiles[i] = *tile == TileType::Wall;
@@ -158,6 +213,102 @@ impl Map {
         map
     }
 
+    /// The town the game starts in, above the dungeon: buildings with a door each around open streets,
+    /// and the dungeon entrance on the east side. "rooms[0]" is the square the player starts in,
+    /// and the rest are the insides of the buildings.
+    pub fn new_town() -> Map {
+        let mut map = Map {
+            tiles: vec![TileType::Floor; MAP_COUNT],
+            rooms: Vec::new(),
+            width: MAP_WIDTH as i32,
+            height: MAP_HEIGHT as i32,
+            depth: 0,
+            revealed_tiles: vec![false; MAP_COUNT],
+            visible_tiles: vec![false; MAP_COUNT],
+            blocked_tiles: vec![false; MAP_COUNT],
+            tile_contents: vec![Vec::new(); MAP_COUNT],
+        };
+
+        const BUILDING_ATTEMPTS: i32 = 200;
+        const MAX_BUILDINGS: usize = 12;
+        const MIN_SIZE: i32 = 4;
+        const MAX_SIZE: i32 = 9;
+
+        // The town wall.
+        for x in 0..map.width {
+            let top = map.xy_idx(x, 0);
+            let bottom = map.xy_idx(x, map.height - 1);
+            map.tiles[top] = TileType::Wall;
+            map.tiles[bottom] = TileType::Wall;
+        }
+        for y in 0..map.height {
+            let left = map.xy_idx(0, y);
+            let right = map.xy_idx(map.width - 1, y);
+            map.tiles[left] = TileType::Wall;
+            map.tiles[right] = TileType::Wall;
+        }
+
+        // Nothing gets built on the square or in front of the dungeon entrance.
+        let square = Rect::new(3, map.height / 2 - 4, 8, 8);
+        let entrance = Rect::new(map.width - 11, map.height / 2 - 4, 8, 8);
+        map.rooms.push(square);
+
+        // The walls of every building so far, as well as the rooms inside them.
+        let mut footprints: Vec<Rect> = Vec::new();
+        let mut rng = RandomNumberGenerator::new();
+        for _ in 0..BUILDING_ATTEMPTS {
+            if map.rooms.len() > MAX_BUILDINGS { break; }
+            let w = rng.range(MIN_SIZE, MAX_SIZE);
+            let h = rng.range(MIN_SIZE, MAX_SIZE);
+            // Leave a street between the buildings and the town wall...
+            let x = rng.range(2, map.width - w - 3);
+            let y = rng.range(2, map.height - h - 3);
+            let building = Rect::new(x, y, w, h);
+
+            // ...and between each building and the next.
+            let street = Rect::new(x - 1, y - 1, w + 3, h + 3);
+            let is_in_the_way = street.intersects(&square)
+                || street.intersects(&entrance)
+                || footprints.iter().any(|footprint| street.intersects(footprint));
+            if is_in_the_way { continue; }
+
+            map.apply_building_to_map(&building);
+            map.rooms.push(building);
+            footprints.push(Rect::new(x, y, w + 1, h + 1));
+        }
+
+        let (stair_x, stair_y) = entrance.center();
+        let stair_index = map.xy_idx(stair_x, stair_y);
+        map.tiles[stair_index] = TileType::Downstairs;
+
+        map
+    }
+
+    // Walls a building in around the room, with a door in the middle of the wall that faces the middle of town.
+    fn apply_building_to_map(&mut self, room: &Rect) {
+        for y in room.y1..=room.y2 + 1 {
+            for x in room.x1..=room.x2 + 1 {
+                let idx = self.xy_idx(x, y);
+                self.tiles[idx] = TileType::Wall;
+            }
+        }
+        self.apply_room_to_map(room);
+
+        let (center_x, center_y) = room.center();
+        let dx = self.width / 2 - center_x;
+        let dy = self.height / 2 - center_y;
+        // The map is about twice as wide as it is tall.
+        let (door_x, door_y) = if dx.abs() > dy.abs() * 2 {
+            if dx > 0 { (room.x2 + 1, center_y) } else { (room.x1, center_y) }
+        } else if dy > 0 {
+            (center_x, room.y2 + 1)
+        } else {
+            (center_x, room.y1)
+        };
+        let door_idx = self.xy_idx(door_x, door_y);
+        self.tiles[door_idx] = TileType::Door;
+    }
+
     pub fn apply_room_to_map(&mut self, room: &Rect) {
         for y in room.y1 + 1..=room.y2 {
             for x in room.x1 + 1..=room.x2 {
@@ -192,12 +343,17 @@ pub fn draw_map(ecs: &World, context: &mut Rltk) {
     let mut y = 0;
     let mut x = 0;
     for (index, tile) in map.tiles.iter().enumerate() {
-        if map.revealed_tiles[index] == true {
+        if map.revealed_tiles[index] {
             let mut fg_color;
             let bg_color;
             let glyph;
             // Set variables for rendering
             match tile {
+                TileType::Door => {
+                    fg_color = RGB::from_f32(0.8, 0.5, 0.2);
+                    bg_color = RGB::from_f32(0.0, 0.0, 0.0);
+                    glyph = rltk::to_cp437('+');
+                }
                 TileType::Floor => {
                     fg_color = RGB::from_f32(0.0, 0.5, 0.5);
                     bg_color = RGB::from_f32(0.0, 0.0, 0.0);
@@ -1,15 +1,47 @@
+use std::collections::HashMap;
+
+use rltk::{BaseMap, RandomNumberGenerator};
 use specs::prelude::*;
 use super::{
+    Ability,
+    AreaOfEffect,
+    Charges,
+    CombatStats,
+    Companion,
+    CompanionOrder,
     Confusion,
+    Faction,
+    FactionTable,
+    InBackpack,
+    InflictsDamage,
+    Item,
+    KeepsDistance,
     Map,
     Monster,
+    PathingMaps,
     Point,
     Position,
+    ProvidesHealing,
+    Ranged,
+    Reaction,
     RunState,
+    UsesItems,
     Viewshed,
+    Wanders,
     WantsToMelee,
+    WantsToPickupItem,
+    WantsToUseItem,
 };
 
+// Companions following the player try to stay at least this close.
+const FOLLOW_DISTANCE: f32 = 2.5;
+
+// What a monster knows about the other creatures on the map at the start of the turn.
+struct Creature {
+    position: Point,
+    faction: String,
+    is_wounded: bool,
+}
 
 pub struct MonsterAI {}
 
@@ -20,12 +52,34 @@ impl<'a> System<'a> for MonsterAI {
         ReadExpect<'a, Entity>,
         ReadExpect<'a, Point>,
         ReadExpect<'a, RunState>,
+        ReadExpect<'a, PathingMaps>,
+        ReadExpect<'a, FactionTable>,
+        WriteExpect<'a, RandomNumberGenerator>,
         WriteExpect<'a, Map>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, InflictsDamage>,
+        ReadStorage<'a, KeepsDistance>,
         ReadStorage<'a, Monster>,
+        ReadStorage<'a, ProvidesHealing>,
+        ReadStorage<'a, Ranged>,
+        ReadStorage<'a, Wanders>,
+        WriteStorage<'a, Ability>,
+        WriteStorage<'a, Companion>,
         WriteStorage<'a, Confusion>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, Viewshed>,
         WriteStorage<'a, WantsToMelee>,
+        WriteStorage<'a, WantsToUseItem>,
+        // Everything needed for monsters to pick up and use items.
+        (
+            ReadStorage<'a, AreaOfEffect>,
+            ReadStorage<'a, Charges>,
+            ReadStorage<'a, InBackpack>,
+            ReadStorage<'a, Item>,
+            ReadStorage<'a, UsesItems>,
+            WriteStorage<'a, WantsToPickupItem>,
+        ),
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -34,19 +88,66 @@ impl<'a> System<'a> for MonsterAI {
             player_entity,
             player_position,
             run_state,
+            pathing_maps,
+            faction_table,
+            mut random_number_generator,
             mut map,
+            combat_stats,
+            factions,
+            inflicts_damage_components,
+            keeps_distance_components,
             monsters,
+            provides_healing_components,
+            ranged_components,
+            wanders_components,
+            mut abilities,
+            mut companions,
             mut confusion,
             mut positions,
             mut viewsheds,
             mut wants_to_melee,
+            mut wants_to_use_item,
+            (
+                area_of_effect_components,
+                charges_components,
+                in_backpacks,
+                items,
+                uses_items_components,
+                mut wants_to_pickup_item,
+            ),
         ) = data;
 
         // Only run the MonsterAI System if the RunState is the MonstersTurn
         if *run_state != RunState::MonsterTurn { return; }
 
-        for (entity, _monster, mut monster_position, mut monster_viewshed)
-        in (&entities, &monsters, &mut positions, &mut viewsheds).join() {
+        // Tick down ability cooldowns, and look up each monsters abilities once rather than per monster.
+        let mut abilities_by_owner: HashMap<Entity, Vec<Entity>> = HashMap::new();
+        for (ability_entity, ability) in (&entities, &mut abilities).join() {
+            if ability.turns_until_ready > 0 {
+                ability.turns_until_ready -= 1;
+            }
+            abilities_by_owner.entry(ability.owner).or_default().push(ability_entity);
+        }
+
+        // Likewise for the items each monster is carrying, leaving out any that have run out of charges.
+        let mut items_by_owner: HashMap<Entity, Vec<Entity>> = HashMap::new();
+        for (item_entity, in_backpack, _item, charges) in (&entities, &in_backpacks, &items, charges_components.maybe()).join() {
+            if charges.is_some_and(|charges| charges.current <= 0) { continue; }
+            items_by_owner.entry(in_backpack.owner).or_default().push(item_entity);
+        }
+
+        // Snapshot every creature that belongs to a faction, so monsters can decide how to react to them.
+        let mut creatures: HashMap<Entity, Creature> = HashMap::new();
+        for (entity, faction, position, combat_stat) in (&entities, &factions, &positions, &combat_stats).join() {
+            creatures.insert(entity, Creature {
+                position: Point::new(position.x, position.y),
+                faction: faction.name.clone(),
+                is_wounded: combat_stat.hp < combat_stat.max_hp,
+            });
+        }
+
+        for (entity, _monster, faction, monster_position, monster_viewshed)
+        in (&entities, &monsters, &factions, &mut positions, &mut viewsheds).join() {
             let mut can_act = true;
 
             let is_confused = confusion.get_mut(entity);
@@ -58,34 +159,293 @@ impl<'a> System<'a> for MonsterAI {
                 can_act = false;
             }
 
-            if can_act {
-                let distance_to_player = rltk::DistanceAlg::Pythagoras.distance2d(
-                    Point::new(monster_position.x, monster_position.y),
-                    *player_position,
-                );
-                if distance_to_player < 1.5 {
-                    wants_to_melee.insert(entity, WantsToMelee { target: *player_entity }).expect("Unable to insert WantsToMelee component.");
-                    return;
-                } else if monster_viewshed.visible_tiles.contains(&*player_position) {
-                    let path_to_player = rltk::a_star_search(
-                        map.xy_idx(monster_position.x, monster_position.y),
-                        map.xy_idx(player_position.x, player_position.y),
-                        &mut *map);
-                    if path_to_player.success && path_to_player.steps.len() > 1 {
-                        // Unblock current position.
-                        let index = map.xy_idx(monster_position.x, monster_position.y);
-                        map.blocked_tiles[index] = false;
-                        // Move along path to the next position.
-                        monster_position.x = path_to_player.steps[1] as i32 % map.width;
-                        monster_position.y = path_to_player.steps[1] as i32 / map.width;
-                        // Block new position.
-                        let index = map.xy_idx(monster_position.x, monster_position.y);
-                        map.blocked_tiles[index] = true;
-                        // Viewshed needs to update now.
-                        monster_viewshed.dirty = true;
+            if !can_act { continue; }
+
+            let monster_point = Point::new(monster_position.x, monster_position.y);
+
+            // Look around, and use the reaction table to decide what to do about everyone in view.
+            let mut attack_target: Option<(Entity, Point)> = None;
+            let mut flee_from: Option<(Entity, Point)> = None;
+            let mut wounded_allies: Vec<Point> = Vec::new();
+            if let Some(me) = creatures.get(&entity) {
+                if me.is_wounded { wounded_allies.push(monster_point); }
+            }
+            for tile in monster_viewshed.visible_tiles.iter() {
+                let index = map.xy_idx(tile.x, tile.y);
+                for other_entity in map.tile_contents[index].iter() {
+                    if *other_entity == entity { continue; }
+                    let other = match creatures.get(other_entity) {
+                        None => continue,
+                        Some(other) => other,
+                    };
+                    match faction_table.reaction(&faction.name, &other.faction) {
+                        Reaction::Attack => {
+                            if is_closer(monster_point, other.position, attack_target) {
+                                attack_target = Some((*other_entity, other.position));
+                            }
+                        }
+                        Reaction::Flee => {
+                            if is_closer(monster_point, other.position, flee_from) {
+                                flee_from = Some((*other_entity, other.position));
+                            }
+                        }
+                        Reaction::Ignore => {
+                            if other.faction == faction.name && other.is_wounded {
+                                wounded_allies.push(other.position);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Companions do as they're told.
+            let mut companion_order: Option<CompanionOrder> = None;
+            if let Some(companion) = companions.get_mut(entity) {
+                if let CompanionOrder::Attack { target } = companion.order {
+                    match creatures.get(&target) {
+                        Some(target_creature) if entities.is_alive(target) => {
+                            attack_target = Some((target, target_creature.position));
+                        }
+                        _ => {
+                            // The target is gone, so go back to following the player.
+                            companion.order = CompanionOrder::Follow;
+                        }
+                    }
+                }
+                companion_order = Some(companion.order.clone());
+            }
+            let is_staying = companion_order == Some(CompanionOrder::Stay);
+
+            // Use an ability if one is ready and has something to aim at.
+            let mut chosen_ability: Option<(Entity, Point)> = None;
+            if let Some(ability_entities) = abilities_by_owner.get(&entity) {
+                for ability_entity in ability_entities.iter() {
+                    let ability = abilities.get(*ability_entity).unwrap();
+                    if ability.turns_until_ready > 0 { continue; }
+                    let range = match ranged_components.get(*ability_entity) {
+                        None => 1,
+                        Some(ranged) => ranged.range,
+                    };
+                    let is_in_range_and_clear = |target: Point| {
+                        rltk::DistanceAlg::Pythagoras.distance2d(monster_point, target) <= range as f32
+                            && map.has_line_of_fire(monster_point, target)
+                    };
+
+                    if provides_healing_components.get(*ability_entity).is_some() {
+                        // Healing abilities are aimed at wounded allies (including the caster).
+                        if let Some(ally_position) = wounded_allies.iter().find(|ally_position| is_in_range_and_clear(**ally_position)) {
+                            chosen_ability = Some((*ability_entity, *ally_position));
+                        }
+                    } else if let Some((_target, target_position)) = attack_target {
+                        // Everything else (ranged attacks, magic missile, confusion) is aimed at the enemy.
+                        let is_offensive = inflicts_damage_components.get(*ability_entity).is_some()
+                            || confusion.get(*ability_entity).is_some();
+                        if is_offensive && is_in_range_and_clear(target_position) {
+                            chosen_ability = Some((*ability_entity, target_position));
+                        }
+                    }
+
+                    if chosen_ability.is_some() { break; }
+                }
+            }
+            if let Some((ability_entity, target)) = chosen_ability {
+                let ability = abilities.get_mut(ability_entity).unwrap();
+                ability.turns_until_ready = ability.cooldown;
+                wants_to_use_item.insert(
+                    entity,
+                    WantsToUseItem {
+                        item: ability_entity,
+                        target: Some(target),
+                    },
+                ).expect("Unable to insert WantsToUseItem component.");
+                continue;
+            }
+
+            // Monsters that know how to use items drink potions when hurt, and read scrolls at their enemies.
+            if uses_items_components.get(entity).is_some() {
+                let mut chosen_item: Option<(Entity, Option<Point>)> = None;
+                if let Some(item_entities) = items_by_owner.get(&entity) {
+                    let mut is_wounded = false;
+                    if let Some(combat_stat) = combat_stats.get(entity) {
+                        is_wounded = combat_stat.hp <= combat_stat.max_hp / 2;
+                    }
+                    for item_entity in item_entities.iter() {
+                        if provides_healing_components.get(*item_entity).is_some() {
+                            if is_wounded {
+                                chosen_item = Some((*item_entity, None));
+                            }
+                        } else if let Some((_target, target_position)) = attack_target {
+                            let is_offensive = inflicts_damage_components.get(*item_entity).is_some()
+                                || confusion.get(*item_entity).is_some();
+                            let range = match ranged_components.get(*item_entity) {
+                                None => continue,
+                                Some(ranged) => ranged.range,
+                            };
+                            let distance = rltk::DistanceAlg::Pythagoras.distance2d(monster_point, target_position);
+                            // Don't get caught in the blast.
+                            let mut is_too_close = false;
+                            if let Some(area_of_effect) = area_of_effect_components.get(*item_entity) {
+                                is_too_close = distance <= area_of_effect.radius as f32;
+                            }
+                            if is_offensive && !is_too_close && distance <= range as f32
+                                && map.has_line_of_fire(monster_point, target_position) {
+                                chosen_item = Some((*item_entity, Some(target_position)));
+                            }
+                        }
+
+                        if chosen_item.is_some() { break; }
+                    }
+                }
+                if let Some((item_entity, target)) = chosen_item {
+                    wants_to_use_item.insert(
+                        entity,
+                        WantsToUseItem {
+                            item: item_entity,
+                            target,
+                        },
+                    ).expect("Unable to insert WantsToUseItem component.");
+                    continue;
+                }
+
+                // Pick up anything lying underfoot, unless there's an enemy close enough to hit it.
+                let is_enemy_adjacent = match attack_target {
+                    None => false,
+                    Some((_target, target_position)) => {
+                        rltk::DistanceAlg::Pythagoras.distance2d(monster_point, target_position) < 1.5
+                    }
+                };
+                if !is_enemy_adjacent {
+                    let index = map.xy_idx(monster_position.x, monster_position.y);
+                    let item_underfoot = map.tile_contents[index]
+                        .iter()
+                        .find(|tile_entity| items.get(**tile_entity).is_some());
+                    if let Some(item_entity) = item_underfoot {
+                        wants_to_pickup_item.insert(
+                            entity,
+                            WantsToPickupItem {
+                                collected_by: entity,
+                                item: *item_entity,
+                            },
+                        ).expect("Unable to insert WantsToPickupItem component.");
+                        continue;
+                    }
+                }
+            }
+
+            // Badly wounded monsters run away from whatever they were fighting.
+            let mut is_badly_wounded = false;
+            if let Some(combat_stat) = combat_stats.get(entity) {
+                is_badly_wounded = combat_stat.hp <= combat_stat.max_hp / 4;
+            }
+            if flee_from.is_none() && is_badly_wounded {
+                flee_from = attack_target;
+            }
+
+            let index = map.xy_idx(monster_position.x, monster_position.y);
+            let mut next_step: Option<usize> = None;
+            let mut melee_target: Option<Entity> = None;
+            if let Some((threat, threat_position)) = flee_from {
+                next_step = step_away(&map, &pathing_maps, index, threat == *player_entity, threat_position);
+            } else if let Some((target, target_position)) = attack_target {
+                let distance = rltk::DistanceAlg::Pythagoras.distance2d(monster_point, target_position);
+
+                // Ranged monsters back off when their target gets too close.
+                let mut is_too_close = false;
+                if let Some(keeps_distance) = keeps_distance_components.get(entity) {
+                    is_too_close = distance < keeps_distance.distance as f32;
+                }
+
+                if is_too_close && !is_staying {
+                    next_step = step_away(&map, &pathing_maps, index, target == *player_entity, target_position);
+                }
+                if next_step.is_none() {
+                    if distance < 1.5 {
+                        melee_target = Some(target);
+                    } else if !is_too_close && !is_staying {
+                        next_step = step_towards(&mut map, &pathing_maps, index, target == *player_entity, target_position);
                     }
                 }
+            } else if companion_order.is_some() {
+                // Companions with nothing to fight catch up with the player, unless told to stay put.
+                let distance_to_player = rltk::DistanceAlg::Pythagoras.distance2d(monster_point, *player_position);
+                if !is_staying && distance_to_player > FOLLOW_DISTANCE {
+                    next_step = pathing_maps.next_step_towards(&map, index);
+                }
+            } else if wanders_components.get(entity).is_some() {
+                // Nothing to do, so wander around.
+                let exits = map.get_available_exits(index);
+                if !exits.is_empty() {
+                    let exit = random_number_generator.range(0, exits.len() as i32) as usize;
+                    next_step = Some(exits[exit].0);
+                }
             }
+
+            if let Some(target) = melee_target {
+                wants_to_melee.insert(entity, WantsToMelee { target }).expect("Unable to insert WantsToMelee component.");
+            } else if let Some(next_index) = next_step {
+                // Unblock current position.
+                map.blocked_tiles[index] = false;
+                // Move to the next position.
+                monster_position.x = next_index as i32 % map.width;
+                monster_position.y = next_index as i32 / map.width;
+                // Block new position.
+                map.blocked_tiles[next_index] = true;
+                // Viewshed needs to update now.
+                monster_viewshed.dirty = true;
+            }
+        }
+    }
+}
+
+fn is_closer(from: Point, candidate: Point, current: Option<(Entity, Point)>) -> bool {
+    match current {
+        None => true,
+        Some((_, current_position)) => {
+            rltk::DistanceAlg::Pythagoras.distance2d(from, candidate)
+                < rltk::DistanceAlg::Pythagoras.distance2d(from, current_position)
+        }
+    }
+}
+
+// The player has shared flow maps; for anyone else, use a plain A* search.
+fn step_towards(map: &mut Map, pathing_maps: &PathingMaps, index: usize, is_player: bool, target: Point) -> Option<usize> {
+    if is_player {
+        return pathing_maps.next_step_towards(map, index);
+    }
+
+    // The target blocks its own tile, so unblock it for the search or there would be no path.
+    let target_index = map.xy_idx(target.x, target.y);
+    let was_blocked = map.blocked_tiles[target_index];
+    map.blocked_tiles[target_index] = false;
+    let path = rltk::a_star_search(index, target_index, &*map);
+    map.blocked_tiles[target_index] = was_blocked;
+
+    if path.success && path.steps.len() > 1 && path.steps[1] != target_index {
+        Some(path.steps[1])
+    } else {
+        None
+    }
+}
+
+// The player has a shared flee map; for anyone else, take the exit that ends up furthest from the threat.
+fn step_away(map: &Map, pathing_maps: &PathingMaps, index: usize, is_player: bool, threat: Point) -> Option<usize> {
+    if is_player {
+        return pathing_maps.next_step_away(map, index);
+    }
+
+    let distance_from_threat = |tile_index: usize| {
+        let tile = Point::new(tile_index as i32 % map.width, tile_index as i32 / map.width);
+        rltk::DistanceAlg::Pythagoras.distance2d(tile, threat)
+    };
+    let mut best: Option<(usize, f32)> = None;
+    let current_distance = distance_from_threat(index);
+    for (exit_index, _cost) in map.get_available_exits(index).iter() {
+        let exit_distance = distance_from_threat(*exit_index);
+        if exit_distance <= current_distance { continue; }
+        match best {
+            Some((_, best_distance)) if best_distance >= exit_distance => {}
+            _ => best = Some((*exit_index, exit_distance)),
         }
     }
-}
\ No newline at end of file
+    best.map(|(exit_index, _)| exit_index)
+}
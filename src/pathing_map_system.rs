@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use rltk::{BaseMap, DijkstraMap, Point};
+use specs::prelude::*;
+use super::{Map, RunState};
+
+// Tiles further than this from the player are treated as unreachable by the flow maps.
+pub const MAX_PATHING_DEPTH: f32 = 1024.0;
+
+// Multiplying the approach map by a value below -1 makes fleeing monsters prefer
+// long escape routes over running into the nearest dead-end.
+const FLEE_MULTIPLIER: f32 = -1.2;
+
+/// Shared Dijkstra flow maps, rebuilt once per monster turn.
+/// Every monster reads from these instead of running its own A* search.
+pub struct PathingMaps {
+    /// Distance from every tile to the player.
+    pub approach: DijkstraMap,
+    /// Derived from the approach map; walking "downhill" leads away from the player.
+    pub flee: DijkstraMap,
+}
+
+impl PathingMaps {
+    // Constructor
+    pub fn new(width: i32, height: i32) -> PathingMaps {
+        PathingMaps {
+            approach: DijkstraMap::new_empty(width, height, MAX_PATHING_DEPTH),
+            flee: DijkstraMap::new_empty(width, height, f32::MAX),
+        }
+    }
+
+    /// The best tile to step onto to get closer to the player, if any.
+    pub fn next_step_towards(&self, map: &Map, index: usize) -> Option<usize> {
+        PathingMaps::lowest_exit(&self.approach, map, index)
+    }
+
+    /// The best tile to step onto to get away from the player, if any.
+    pub fn next_step_away(&self, map: &Map, index: usize) -> Option<usize> {
+        PathingMaps::lowest_exit(&self.flee, map, index)
+    }
+
+    // Picks the unblocked neighbour with the lowest value, as long as it improves on the current tile.
+    // Unlike "DijkstraMap::find_lowest_exit(...)", this never moves a monster sideways or backwards.
+    fn lowest_exit(flow_map: &DijkstraMap, map: &Map, index: usize) -> Option<usize> {
+        let current_value = flow_map.map[index];
+        let mut best: Option<(usize, f32)> = None;
+        for (exit_index, _cost) in map.get_available_exits(index).iter() {
+            let exit_value = flow_map.map[*exit_index];
+            if exit_value >= current_value { continue; }
+            match best {
+                Some((_, best_value)) if best_value <= exit_value => {}
+                _ => best = Some((*exit_index, exit_value)),
+            }
+        }
+        best.map(|(exit_index, _)| exit_index)
+    }
+
+    /// Rebuilds both flow maps around the given position.
+    pub fn rebuild(&mut self, map: &Map, target: Point) {
+        // The map can change size between levels, so start from scratch if it did.
+        if self.approach.map.len() != map.tiles.len() {
+            *self = PathingMaps::new(map.width, map.height);
+        }
+
+        // Approach Map
+        // Built on terrain only, so a queue of monsters in a corridor doesn't cut the map in half.
+        DijkstraMap::clear(&mut self.approach);
+        let target_index = map.xy_idx(target.x, target.y);
+        self.approach.map[target_index] = 0.0;
+        settle_flow_map(&mut self.approach.map, vec![target_index], map, MAX_PATHING_DEPTH);
+
+        // Flee Map
+        // Invert the approach map and let it settle, so that monsters run towards
+        // open areas far from the player rather than simply the furthest adjacent tile.
+        let mut starts: Vec<usize> = Vec::new();
+        for (index, value) in self.approach.map.iter().enumerate() {
+            if *value < MAX_PATHING_DEPTH {
+                self.flee.map[index] = value * FLEE_MULTIPLIER;
+                starts.push(index);
+            } else {
+                self.flee.map[index] = f32::MAX;
+            }
+        }
+        settle_flow_map(&mut self.flee.map, starts, map, f32::MAX);
+    }
+}
+
+// Dijkstra's algorithm over the terrain, starting from tiles that already hold a value.
+// Using a priority queue means every tile is only expanded once, which matters on large maps.
+fn settle_flow_map(values: &mut [f32], starts: Vec<usize>, map: &Map, max_depth: f32) {
+    let mut open_list: BinaryHeap<OpenTile> = starts
+        .into_iter()
+        .map(|index| OpenTile { value: values[index], index })
+        .collect();
+
+    while let Some(OpenTile { value, index }) = open_list.pop() {
+        // A shorter route to this tile has already been expanded.
+        if value > values[index] { continue; }
+        for (exit_index, cost) in map.get_terrain_exits(index).iter() {
+            let new_value = value + cost;
+            if new_value < values[*exit_index] && new_value < max_depth {
+                values[*exit_index] = new_value;
+                open_list.push(OpenTile { value: new_value, index: *exit_index });
+            }
+        }
+    }
+}
+
+// Entry in the open list, ordered so that the BinaryHeap pops the lowest value first.
+struct OpenTile {
+    value: f32,
+    index: usize,
+}
+
+impl PartialEq for OpenTile {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for OpenTile {}
+
+impl PartialOrd for OpenTile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenTile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.value.total_cmp(&self.value)
+    }
+}
+
+pub struct PathingMapSystem {}
+
+impl<'a> System<'a> for PathingMapSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, Point>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, PathingMaps>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, player_position, run_state, mut pathing_maps) = data;
+
+        // The flow maps are only consumed by the MonsterAI System, so only rebuild them on the monsters turn.
+        if *run_state != RunState::MonsterTurn { return; }
+
+        pathing_maps.rebuild(&map, *player_position);
+    }
+}
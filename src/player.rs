@@ -1,17 +1,33 @@
-use rltk::{Point, Rltk, VirtualKeyCode};
+use rltk::{Point, RandomNumberGenerator, Rltk, VirtualKeyCode};
 use specs::prelude::*;
-use std::cmp::{max, min};
 use crate::TileType;
+use crate::gui::{ContainerSide, ShopMode};
+use crate::ranged_combat_system::{find_ammo, shootable_targets};
 use super::{
+    container,
+    raws,
     CombatStats,
+    Companion,
+    CompanionOrder,
+    Confusion,
+    Encumbered,
+    Faction,
+    FactionTable,
     GameLog,
+    HungerClock,
+    HungerState,
+    InBackpack,
     Item,
+    KnownSpell,
     Map,
-    Monster,
+    Peaceful,
     Player,
     Position,
+    RangedWeapon,
+    Reaction,
     RunState,
     State,
+    Vendor,
     Viewshed,
     WantsToMelee,
     WantsToPickupItem};
@@ -27,32 +43,32 @@ pub fn player_input(game_state: &mut State, context: &mut Rltk) -> RunState {
                 // Cardinal Directions
                 VirtualKeyCode::Left |
                 VirtualKeyCode::Numpad4 |
-                VirtualKeyCode::H => try_move_player(-1, 0, &mut game_state.ecs),
+                VirtualKeyCode::H => return try_move_player(-1, 0, &mut game_state.ecs),
 
                 VirtualKeyCode::Right |
                 VirtualKeyCode::Numpad6 |
-                VirtualKeyCode::L => try_move_player(1, 0, &mut game_state.ecs),
+                VirtualKeyCode::L => return try_move_player(1, 0, &mut game_state.ecs),
 
                 VirtualKeyCode::Up |
                 VirtualKeyCode::Numpad8 |
-                VirtualKeyCode::K => try_move_player(0, -1, &mut game_state.ecs),
+                VirtualKeyCode::K => return try_move_player(0, -1, &mut game_state.ecs),
 
                 VirtualKeyCode::Down |
                 VirtualKeyCode::Numpad2 |
-                VirtualKeyCode::J => try_move_player(0, 1, &mut game_state.ecs),
+                VirtualKeyCode::J => return try_move_player(0, 1, &mut game_state.ecs),
 
                 // Diagonals Directions
                 VirtualKeyCode::Numpad9 |
-                VirtualKeyCode::U => try_move_player(1, -1, &mut game_state.ecs),
+                VirtualKeyCode::U => return try_move_player(1, -1, &mut game_state.ecs),
 
                 VirtualKeyCode::Numpad7 |
-                VirtualKeyCode::Y => try_move_player(-1, -1, &mut game_state.ecs),
+                VirtualKeyCode::Y => return try_move_player(-1, -1, &mut game_state.ecs),
 
                 VirtualKeyCode::Numpad3 |
-                VirtualKeyCode::N => try_move_player(1, 1, &mut game_state.ecs),
+                VirtualKeyCode::N => return try_move_player(1, 1, &mut game_state.ecs),
 
                 VirtualKeyCode::Numpad1 |
-                VirtualKeyCode::B => try_move_player(-1, 1, &mut game_state.ecs),
+                VirtualKeyCode::B => return try_move_player(-1, 1, &mut game_state.ecs),
 
                 // Vertical Directions (Up / Down)
                 VirtualKeyCode::Period => {
@@ -64,14 +80,40 @@ pub fn player_input(game_state: &mut State, context: &mut Rltk) -> RunState {
                 // === Interactions ===
                 // Item Pickup
                 VirtualKeyCode::G => try_pickup_item(&mut game_state.ecs),
+                // Open a Chest, Barrel or Corpse
+                VirtualKeyCode::C => return try_open_container(&mut game_state.ecs),
 
                 // === UI ===
                 VirtualKeyCode::I => return RunState::ShowInventory,
                 VirtualKeyCode::D => return RunState::ShowDropItem,
+                VirtualKeyCode::T => return RunState::ShowThrowItem,
+                VirtualKeyCode::F => return try_fire(&mut game_state.ecs),
+                VirtualKeyCode::Z => {
+                    if knows_spells(&game_state.ecs) {
+                        return RunState::ShowSpells;
+                    }
+                    let mut game_log = game_state.ecs.fetch_mut::<GameLog>();
+                    game_log.entries.push("You don't know any spells.".to_string());
+                    return RunState::AwaitingInput;
+                }
+                VirtualKeyCode::O => {
+                    if has_companions(&game_state.ecs) {
+                        return RunState::ShowCompanionOrders;
+                    }
+                    let mut game_log = game_state.ecs.fetch_mut::<GameLog>();
+                    game_log.entries.push("You have no companions to give orders to.".to_string());
+                    return RunState::AwaitingInput;
+                }
 
                 // === State ===
                 VirtualKeyCode::Escape => return RunState::SaveGame,
 
+                // === Debug ===
+                VirtualKeyCode::F5 => {
+                    raws::reload_raws(&mut game_state.ecs);
+                    return RunState::AwaitingInput;
+                }
+
                 // === Skip Turn ===
                 VirtualKeyCode::Space => return skip_turn(&mut game_state.ecs),
                 VirtualKeyCode::Numpad5 => return skip_turn(&mut game_state.ecs),
@@ -87,15 +129,22 @@ pub fn player_input(game_state: &mut State, context: &mut Rltk) -> RunState {
     RunState::PlayerTurn
 }
 
-fn try_move_player(dx: i32, dy: i32, ecs: &mut World) {
+// Moves the player, or attacks, swaps places or trades with whoever is in the way.
+fn try_move_player(dx: i32, dy: i32, ecs: &mut World) -> RunState {
+    let (dx, dy) = stumble_if_confused(dx, dy, ecs);
     let entities = ecs.entities();
     let map = ecs.fetch::<Map>();
     let combat_stats = ecs.read_storage::<CombatStats>();
+    let companions = ecs.read_storage::<Companion>();
+    let factions = ecs.read_storage::<Faction>();
+    let peacefuls = ecs.read_storage::<Peaceful>();
+    let vendors = ecs.read_storage::<Vendor>();
     let mut players = ecs.write_storage::<Player>();
     let mut positions = ecs.write_storage::<Position>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
 
+    let mut swap_with: Option<(Entity, i32, i32)> = None;
     for (entity, _player, position, viewshed)
     in (&entities, &mut players, &mut positions, &mut viewsheds).join() {
         let x = position.x + dx;
@@ -104,29 +153,142 @@ fn try_move_player(dx: i32, dy: i32, ecs: &mut World) {
 
         // Check if the tile contains an Entity with CombatStats
         for potential_target in map.tile_contents[idx].iter() {
+            // Swap places with companions, and with anyone peaceful.
+            if companions.get(*potential_target).is_some() || peacefuls.contains(*potential_target) {
+                swap_with = Some((*potential_target, position.x, position.y));
+                continue;
+            }
+            // Shopkeepers trade instead of fighting.
+            if vendors.contains(*potential_target) {
+                return RunState::ShowShop { shopkeeper: *potential_target, mode: ShopMode::Buy };
+            }
+            // Don't attack allies.
+            if let Some(faction) = factions.get(*potential_target) {
+                if faction.name == "Player" { continue; }
+            }
             let target = combat_stats.get(*potential_target);
-            match target
-            {
-                // Tile contains an Entity with CombatStats, add a WantsToMelee component to the player, with the potential target as the target.
-                Some(_target) => {
-                    wants_to_melee.insert(entity, WantsToMelee { target: *potential_target }).expect("Add target failed");
-                    return;
-                }
-                None => {}
+            // Tile contains an Entity with CombatStats, add a WantsToMelee component to the player, with the potential target as the target.
+            if let Some(_target) = target {
+                wants_to_melee.insert(entity, WantsToMelee { target: *potential_target }).expect("Add target failed");
+                return RunState::PlayerTurn;
             }
         }
 
-        // Check the tile isn't blocked
-        if !map.blocked_tiles[idx] {
+        // Check the tile isn't blocked (a companion or anyone peaceful in the way will move)
+        if !map.blocked_tiles[idx] || swap_with.is_some() {
             // Check you haven't left the screen.
-            position.x = min(79, max(0, position.x + dx));
-            position.y = min(49, max(0, position.y + dy));
+            position.x = (position.x + dx).clamp(0, 79);
+            position.y = (position.y + dy).clamp(0, 49);
             viewshed.dirty = true;
             let mut player_position = ecs.write_resource::<Point>();
             player_position.x = position.x;
             player_position.y = position.y;
         }
     }
+
+    // Move whoever was in the way into the player's old position.
+    if let Some((swapped_entity, x, y)) = swap_with {
+        if let Some(swapped_position) = positions.get_mut(swapped_entity) {
+            swapped_position.x = x;
+            swapped_position.y = y;
+        }
+        if let Some(swapped_viewshed) = viewsheds.get_mut(swapped_entity) {
+            swapped_viewshed.dirty = true;
+        }
+    }
+    RunState::PlayerTurn
+}
+
+/// Whether the monsters get another turn before the player can act again.
+/// An encumbered player loses every other turn.
+pub fn is_player_turn_lost(ecs: &mut World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    let mut encumbered_components = ecs.write_storage::<Encumbered>();
+    match encumbered_components.get_mut(*player_entity) {
+        None => false,
+        Some(encumbered) => {
+            encumbered.monster_turns += 1;
+            encumbered.monster_turns % 3 == 2
+        }
+    }
+}
+
+/// Gives the same order to all of the player's companions.
+pub fn order_companions(ecs: &mut World, order: CompanionOrder) {
+    let mut companions = ecs.write_storage::<Companion>();
+    for companion in (&mut companions).join() {
+        companion.order = order.clone();
+    }
+
+    let mut game_log = ecs.fetch_mut::<GameLog>();
+    match order {
+        CompanionOrder::Follow => game_log.entries.push("You tell your companions to follow you.".to_string()),
+        CompanionOrder::Stay => game_log.entries.push("You tell your companions to stay here.".to_string()),
+        CompanionOrder::Attack { .. } => game_log.entries.push("You tell your companions to attack!".to_string()),
+    }
+}
+
+/// Orders the companions to attack whatever is standing on the target tile.
+pub fn order_companions_to_attack(ecs: &mut World, target: Point) {
+    let mut attack_target: Option<Entity> = None;
+    {
+        let map = ecs.fetch::<Map>();
+        let combat_stats = ecs.read_storage::<CombatStats>();
+        let factions = ecs.read_storage::<Faction>();
+        let index = map.xy_idx(target.x, target.y);
+        for entity in map.tile_contents[index].iter() {
+            let is_ally = match factions.get(*entity) {
+                None => false,
+                Some(faction) => faction.name == "Player",
+            };
+            if combat_stats.get(*entity).is_some() && !is_ally {
+                attack_target = Some(*entity);
+            }
+        }
+    }
+
+    match attack_target {
+        None => {
+            let mut game_log = ecs.fetch_mut::<GameLog>();
+            game_log.entries.push("There is nothing there to attack.".to_string());
+        }
+        Some(target) => order_companions(ecs, CompanionOrder::Attack { target }),
+    }
+}
+
+fn has_companions(ecs: &World) -> bool {
+    let companions = ecs.read_storage::<Companion>();
+    companions.join().next().is_some()
+}
+
+fn knows_spells(ecs: &World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    let known_spells = ecs.read_storage::<KnownSpell>();
+    known_spells.join().any(|known_spell| known_spell.owner == *player_entity)
+}
+
+// Confused players (e.g. from an Orc Shaman's spell) move in a random direction instead.
+fn stumble_if_confused(dx: i32, dy: i32, ecs: &mut World) -> (i32, i32) {
+    let player_entity = ecs.fetch::<Entity>();
+    let mut confusion_components = ecs.write_storage::<Confusion>();
+
+    let mut is_confused = false;
+    let mut is_confusion_over = false;
+    if let Some(confusion) = confusion_components.get_mut(*player_entity) {
+        is_confused = true;
+        confusion.turns -= 1;
+        is_confusion_over = confusion.turns < 1;
+    }
+    if is_confusion_over {
+        confusion_components.remove(*player_entity);
+    }
+    if !is_confused { return (dx, dy); }
+
+    let mut game_log = ecs.fetch_mut::<GameLog>();
+    game_log.entries.push("You stumble around in confusion.".to_string());
+    const DIRECTIONS: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+    let mut random_number_generator = ecs.write_resource::<RandomNumberGenerator>();
+    DIRECTIONS[random_number_generator.range(0, 8) as usize]
 }
 
 fn try_pickup_item(ecs: &mut World) {
@@ -162,6 +324,20 @@ fn try_pickup_item(ecs: &mut World) {
     }
 }
 
+fn try_open_container(ecs: &mut World) -> RunState {
+    match container::reachable_container(ecs) {
+        None => {
+            let mut game_log = ecs.fetch_mut::<GameLog>();
+            game_log.entries.push("There is nothing here to open.".to_string());
+            RunState::AwaitingInput
+        }
+        Some(container) if container::try_unlock(ecs, container) => {
+            RunState::ShowContainer { container, side: ContainerSide::Container }
+        }
+        Some(_container) => RunState::AwaitingInput,
+    }
+}
+
 fn try_use_stairs(ecs: &mut World) -> bool {
     let player_position = ecs.fetch::<Point>();
     let map = ecs.fetch::<Map>();
@@ -175,28 +351,73 @@ fn try_use_stairs(ecs: &mut World) -> bool {
     }
 }
 
+fn try_fire(ecs: &mut World) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let weapons: Vec<(Entity, RangedWeapon)> = {
+        let entities = ecs.entities();
+        let in_backpacks = ecs.read_storage::<InBackpack>();
+        let ranged_weapons = ecs.read_storage::<RangedWeapon>();
+        (&entities, &in_backpacks, &ranged_weapons)
+            .join()
+            .filter(|(_weapon, in_backpack, _ranged_weapon)| in_backpack.owner == player_entity)
+            .map(|(weapon, _in_backpack, ranged_weapon)| (weapon, ranged_weapon.clone()))
+            .collect()
+    };
+
+    // The first weapon there's ammunition for is the one fired.
+    let ready_weapon = weapons.iter().find(|(weapon, _ranged_weapon)| find_ammo(ecs, player_entity, *weapon).is_some());
+    let mut game_log = ecs.fetch_mut::<GameLog>();
+    let (weapon, ranged_weapon) = match (ready_weapon, weapons.first()) {
+        (Some(ready_weapon), _) => ready_weapon,
+        (None, Some((_weapon, ranged_weapon))) => {
+            game_log.entries.push(format!("You're out of {}s.", ranged_weapon.ammo));
+            return RunState::AwaitingInput;
+        }
+        (None, None) => {
+            game_log.entries.push("You have nothing to shoot with.".to_string());
+            return RunState::AwaitingInput;
+        }
+    };
+
+    match shootable_targets(ecs, ranged_weapon.range).first() {
+        None => {
+            game_log.entries.push("There is nothing in range you can hit.".to_string());
+            RunState::AwaitingInput
+        }
+        Some(target) => RunState::ShowFireTargeting { weapon: *weapon, target: *target },
+    }
+}
+
 fn skip_turn(ecs: &mut World) -> RunState {
     let map = ecs.fetch::<Map>();
     let player_entity = ecs.fetch::<Entity>();
+    let faction_table = ecs.fetch::<FactionTable>();
     let viewshed_components = ecs.read_storage::<Viewshed>();
-    let monsters = ecs.read_storage::<Monster>();
+    let factions = ecs.read_storage::<Faction>();
 
-    // Check for nearby monsters.
+    // Check for nearby hostile monsters.
     let mut can_heal = true;
     let player_viewshed = viewshed_components.get(*player_entity).unwrap();
     for tile in player_viewshed.visible_tiles.iter() {
         let index = map.xy_idx(tile.x, tile.y);
         for entity in map.tile_contents[index].iter() {
-            let monster = monsters.get(*entity);
-            match monster {
-                None => {}
-                Some(_) => {
+            let faction = factions.get(*entity);
+            if let Some(faction) = faction {
+                if faction_table.reaction(&faction.name, "Player") == Reaction::Attack {
                     can_heal = false;
                 }
             }
         }
     }
 
+    // Hunger gets in the way of resting.
+    let hunger_clocks = ecs.read_storage::<HungerClock>();
+    if let Some(hunger_clock) = hunger_clocks.get(*player_entity) {
+        if hunger_clock.state == HungerState::Hungry || hunger_clock.state == HungerState::Starving {
+            can_heal = false;
+        }
+    }
+
     // Heal if no monsters are around.
     if can_heal {
         let mut combat_stats_components = ecs.write_storage::<CombatStats>();
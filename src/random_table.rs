@@ -1,7 +1,15 @@
 use rltk::RandomNumberGenerator;
 
+// What rolling an entry gives: either a name, or another roll on a nested table.
+#[derive(Clone)]
+enum RandomOutcome {
+    Name(String),
+    Table(RandomTable),
+}
+
+#[derive(Clone)]
 pub struct RandomEntry {
-    name: String,
+    outcome: RandomOutcome,
     weight: i32,
 }
 
@@ -9,13 +17,13 @@ impl RandomEntry {
     // Constructor
     pub fn new<S: ToString>(name: S, weight: i32) -> RandomEntry {
         RandomEntry {
-            name: name.to_string(),
+            outcome: RandomOutcome::Name(name.to_string()),
             weight,
         }
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct RandomTable {
     entries: Vec<RandomEntry>,
     total_weight: i32,
@@ -30,26 +38,243 @@ impl RandomTable {
         }
     }
 
+    /// Entries with a weight of 0 or less could never be rolled, so they're left out.
     pub fn add<S: ToString>(mut self, name: S, weight: i32) -> RandomTable {
-        self.total_weight += weight;
-        self.entries.push(RandomEntry::new(name.to_string(), weight));
+        if weight > 0 {
+            self.total_weight += weight;
+            self.entries.push(RandomEntry::new(name.to_string(), weight));
+        }
+        self
+    }
+
+    /// Adds a whole table as a single entry (e.g. "any scroll"). Rolling it rolls again on that table.
+    pub fn add_table(mut self, table: RandomTable, weight: i32) -> RandomTable {
+        if weight > 0 && !table.is_empty() {
+            self.total_weight += weight;
+            self.entries.push(RandomEntry { outcome: RandomOutcome::Table(table), weight });
+        }
         self
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Picks an entry with a chance proportional to its weight. An empty table rolls "None".
     pub fn roll(&self, rng: &mut RandomNumberGenerator) -> String {
         if self.total_weight == 0 { return "None".to_string(); }
-        let mut roll = rng.roll_dice(1, self.total_weight) - 1;
-        let mut index: usize = 0;
+        let mut roll = rng.range(0, self.total_weight);
 
-        while roll > 0 {
-            if roll < self.entries[index].weight {
-                return self.entries[index].name.clone();
+        for entry in self.entries.iter() {
+            if roll < entry.weight {
+                return match &entry.outcome {
+                    RandomOutcome::Name(name) => name.clone(),
+                    RandomOutcome::Table(table) => table.roll(rng),
+                };
             }
-
-            roll -= self.entries[index].weight;
-            index += 1;
+            roll -= entry.weight;
         }
 
+        // Unreachable, since the roll is always below the total weight.
         "None".to_string()
     }
-}
\ No newline at end of file
+
+    /// Removes every entry with this name, including from nested tables.
+    /// Nested tables left with nothing in them are removed too.
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain_mut(|entry| match &mut entry.outcome {
+            RandomOutcome::Name(entry_name) => entry_name != name,
+            RandomOutcome::Table(table) => {
+                table.remove(name);
+                !table.is_empty()
+            }
+        });
+        self.total_weight = self.entries.iter().map(|entry| entry.weight).sum();
+    }
+
+    /// Rolls up to "count" different names, removing each one from the table once it has come up.
+    /// "None" is never returned; if the table runs out, fewer names are returned.
+    pub fn sample_without_replacement(&self, count: usize, rng: &mut RandomNumberGenerator) -> Vec<String> {
+        let mut remaining = self.clone();
+        let mut sample: Vec<String> = Vec::new();
+        while sample.len() < count && !remaining.is_empty() {
+            let name = remaining.roll(rng);
+            remaining.remove(&name);
+            if name != "None" {
+                sample.push(name);
+            }
+        }
+        sample
+    }
+
+    /// The chance of rolling each name, with nested tables flattened out.
+    pub fn probabilities(&self) -> Vec<(String, f32)> {
+        let mut probabilities: Vec<(String, f32)> = Vec::new();
+        if self.total_weight == 0 { return probabilities; }
+
+        for entry in self.entries.iter() {
+            let entry_chance = entry.weight as f32 / self.total_weight as f32;
+            let outcomes = match &entry.outcome {
+                RandomOutcome::Name(name) => vec![(name.clone(), 1.0)],
+                RandomOutcome::Table(table) => table.probabilities(),
+            };
+            for (name, chance) in outcomes {
+                match probabilities.iter_mut().find(|(known_name, _chance)| *known_name == name) {
+                    Some((_name, known_chance)) => *known_chance += entry_chance * chance,
+                    None => probabilities.push((name, entry_chance * chance)),
+                }
+            }
+        }
+        probabilities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use rltk::RandomNumberGenerator;
+    use crate::spawn_analysis::chi_square_critical_value;
+    use super::RandomTable;
+
+    const ROLLS: i32 = 20_000;
+
+    // How many times each name came up in ROLLS rolls of the table.
+    fn roll_counts(table: &RandomTable, rng: &mut RandomNumberGenerator) -> HashMap<String, i32> {
+        let mut counts: HashMap<String, i32> = HashMap::new();
+        for _roll in 0..ROLLS {
+            *counts.entry(table.roll(rng)).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // Fails unless the counts match the table's probabilities, within what chance allows 99.9% of the time.
+    fn assert_follows_probabilities(table: &RandomTable, counts: &HashMap<String, i32>) {
+        let probabilities = table.probabilities();
+        for name in counts.keys() {
+            assert!(probabilities.iter().any(|(expected_name, _chance)| expected_name == name), "rolled unknown name {}", name);
+        }
+        let chi_square: f64 = probabilities
+            .iter()
+            .map(|(name, chance)| {
+                let expected = *chance as f64 * ROLLS as f64;
+                let observed = *counts.get(name).unwrap_or(&0) as f64;
+                (observed - expected).powi(2) / expected
+            })
+            .sum();
+        let critical_value = chi_square_critical_value(probabilities.len() - 1);
+        assert!(chi_square < critical_value, "chi-squared {} is over {}", chi_square, critical_value);
+    }
+
+    fn assert_probabilities(table: &RandomTable, expected: &[(&str, f32)]) {
+        let probabilities = table.probabilities();
+        assert_eq!(probabilities.len(), expected.len());
+        for (name, expected_chance) in expected {
+            let chance = probabilities.iter().find(|(known_name, _chance)| known_name == name).map(|(_name, chance)| *chance);
+            assert!(chance.is_some_and(|chance| (chance - expected_chance).abs() < 1e-6), "{}: {:?}", name, chance);
+        }
+    }
+
+    #[test]
+    fn rolls_follow_the_weights() {
+        let table = RandomTable::new().add("Goblin", 1).add("Orc", 2).add("Health Potion", 7);
+        assert_probabilities(&table, &[("Goblin", 0.1), ("Orc", 0.2), ("Health Potion", 0.7)]);
+        let mut rng = RandomNumberGenerator::seeded(42);
+        assert_follows_probabilities(&table, &roll_counts(&table, &mut rng));
+    }
+
+    #[test]
+    fn empty_table_rolls_none() {
+        let mut rng = RandomNumberGenerator::seeded(42);
+        let table = RandomTable::new();
+        assert!(table.is_empty());
+        assert!(table.probabilities().is_empty());
+        assert_eq!(table.roll(&mut rng), "None");
+
+        let zero_weight_table = RandomTable::new().add("Goblin", 0).add_table(RandomTable::new().add("Orc", 0), 5);
+        assert!(zero_weight_table.is_empty());
+        assert_eq!(zero_weight_table.roll(&mut rng), "None");
+    }
+
+    #[test]
+    fn weights_of_zero_or_less_are_left_out() {
+        let table = RandomTable::new()
+            .add("Goblin", 0)
+            .add("Orc", -3)
+            .add("Bat", 4)
+            .add_table(RandomTable::new().add("Health Potion", 1), 0)
+            .add_table(RandomTable::new().add("Food Ration", 1), -1);
+        assert_probabilities(&table, &[("Bat", 1.0)]);
+        let mut rng = RandomNumberGenerator::seeded(42);
+        let counts = roll_counts(&table, &mut rng);
+        assert_eq!(counts.get("Bat"), Some(&ROLLS));
+    }
+
+    #[test]
+    fn nested_tables_roll_again() {
+        let scrolls = RandomTable::new().add("Magic Missile Scroll", 1).add("Fireball Scroll", 3);
+        let table = RandomTable::new().add("Gold", 1).add_table(scrolls, 1);
+        assert_probabilities(&table, &[("Gold", 0.5), ("Magic Missile Scroll", 0.125), ("Fireball Scroll", 0.375)]);
+        let mut rng = RandomNumberGenerator::seeded(42);
+        assert_follows_probabilities(&table, &roll_counts(&table, &mut rng));
+    }
+
+    #[test]
+    fn nested_tables_merge_repeated_names() {
+        let table = RandomTable::new().add("Gold", 1).add_table(RandomTable::new().add("Gold", 1).add("Arrow", 1), 1);
+        assert_probabilities(&table, &[("Gold", 0.75), ("Arrow", 0.25)]);
+    }
+
+    #[test]
+    fn remove_prunes_nested_tables_left_empty() {
+        let mut table = RandomTable::new()
+            .add("Gold", 1)
+            .add_table(RandomTable::new().add("Arrow", 1), 2)
+            .add_table(RandomTable::new().add("Bolt", 1).add("Arrow", 1), 1);
+        table.remove("Arrow");
+        assert_probabilities(&table, &[("Gold", 0.5), ("Bolt", 0.5)]);
+
+        table.remove("Bolt");
+        assert_probabilities(&table, &[("Gold", 1.0)]);
+        let mut rng = RandomNumberGenerator::seeded(42);
+        assert_eq!(roll_counts(&table, &mut rng).get("Gold"), Some(&ROLLS));
+
+        table.remove("Gold");
+        assert!(table.is_empty());
+        assert_eq!(table.roll(&mut rng), "None");
+    }
+
+    #[test]
+    fn sample_without_replacement_never_repeats() {
+        let table = RandomTable::new()
+            .add("None", 5)
+            .add("Gold", 10)
+            .add("Arrow", 1)
+            .add_table(RandomTable::new().add("Bolt", 1).add("Arrow", 1), 1);
+        let mut rng = RandomNumberGenerator::seeded(42);
+        for _sample in 0..100 {
+            let mut sample = table.sample_without_replacement(2, &mut rng);
+            assert_eq!(sample.len(), 2);
+            assert!(!sample.contains(&"None".to_string()));
+            sample.dedup();
+            assert_eq!(sample.len(), 2);
+        }
+
+        // Asking for more than the table holds gives everything in it, once each.
+        let mut sample = table.sample_without_replacement(10, &mut rng);
+        sample.sort();
+        assert_eq!(sample, vec!["Arrow", "Bolt", "Gold"]);
+        assert!(RandomTable::new().sample_without_replacement(3, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn sample_without_replacement_picks_by_weight() {
+        let table = RandomTable::new().add("Goblin", 1).add("Orc", 2).add("Health Potion", 7);
+        let mut rng = RandomNumberGenerator::seeded(42);
+        let mut counts: HashMap<String, i32> = HashMap::new();
+        for _sample in 0..ROLLS {
+            let first = table.sample_without_replacement(1, &mut rng).remove(0);
+            *counts.entry(first).or_insert(0) += 1;
+        }
+        assert_follows_probabilities(&table, &counts);
+    }
+}
@@ -0,0 +1,180 @@
+use rltk::{FontCharType, Point, RandomNumberGenerator, RGB};
+use specs::prelude::*;
+use crate::effects_system::{EffectQueue, EffectType, Targets};
+use crate::{
+    CombatStats,
+    Companion,
+    InBackpack,
+    Map,
+    Monster,
+    Name,
+    Position,
+    RangedWeapon,
+    Renderer,
+    WantsToShoot,
+    game_log::GameLog,
+};
+
+// The percentage chance of a shot's ammunition surviving to be picked up again.
+const AMMO_RECOVERY_CHANCE: i32 = 50;
+
+/// Something flying across the map, which the main loop draws one tile per frame before the turn carries on.
+pub struct ProjectileAnimation {
+    pub path: Vec<Point>,
+    pub glyph: FontCharType,
+    pub fg: RGB,
+}
+
+impl ProjectileAnimation {
+    // Constructor
+    pub fn new() -> ProjectileAnimation {
+        ProjectileAnimation {
+            path: Vec::new(),
+            glyph: rltk::to_cp437('*'),
+            fg: RGB::named(rltk::WHITE),
+        }
+    }
+
+    /// Animates the entity with this renderer flying along the path.
+    pub fn start(&mut self, path: Vec<Point>, renderer: Option<&Renderer>) {
+        self.path = path;
+        if let Some(renderer) = renderer {
+            self.glyph = renderer.glyph;
+            self.fg = renderer.fg;
+        }
+    }
+}
+
+/// The item in the owner's backpack that the weapon shoots, if they have any left.
+pub fn find_ammo(ecs: &World, owner: Entity, weapon: Entity) -> Option<Entity> {
+    let ranged_weapons = ecs.read_storage::<RangedWeapon>();
+    let ranged_weapon = ranged_weapons.get(weapon)?;
+    let entities = ecs.entities();
+    let in_backpacks = ecs.read_storage::<InBackpack>();
+    let names = ecs.read_storage::<Name>();
+    (&entities, &in_backpacks, &names)
+        .join()
+        .find(|(_item, in_backpack, name)| in_backpack.owner == owner && name.name == ranged_weapon.ammo)
+        .map(|(item, _in_backpack, _name)| item)
+}
+
+/// The monsters the player can see within range with nothing in the way, closest first.
+/// Companions are left out, so they never need to be cycled past.
+pub fn shootable_targets(ecs: &World, range: i32) -> Vec<Entity> {
+    let map = ecs.fetch::<Map>();
+    let player_position = *ecs.fetch::<Point>();
+    let entities = ecs.entities();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let companions = ecs.read_storage::<Companion>();
+    let monsters = ecs.read_storage::<Monster>();
+    let positions = ecs.read_storage::<Position>();
+
+    let mut targets: Vec<(Entity, f32)> = Vec::new();
+    for (entity, _combat_stats, _monster, position) in (&entities, &combat_stats, &monsters, &positions).join() {
+        if companions.contains(entity) { continue; }
+        let target_position = Point::new(position.x, position.y);
+        if !map.visible_tiles[map.xy_idx(position.x, position.y)] { continue; }
+        let distance = rltk::DistanceAlg::Pythagoras.distance2d(player_position, target_position);
+        if distance > range as f32 { continue; }
+        // Walls and anyone standing in between block the shot.
+        if map.projectile_path(player_position, target_position).last() != Some(&target_position) { continue; }
+        targets.push((entity, distance));
+    }
+    targets.sort_by(|a, b| a.1.total_cmp(&b.1));
+    targets.into_iter().map(|(entity, _distance)| entity).collect()
+}
+
+pub struct RangedCombatSystem {}
+
+impl<'a> System<'a> for RangedCombatSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, ProjectileAnimation>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, RangedWeapon>,
+        ReadStorage<'a, Renderer>,
+        WriteStorage<'a, InBackpack>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, WantsToShoot>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            map,
+            mut effect_queue,
+            mut game_log,
+            mut projectile_animation,
+            mut rng,
+            combat_stats,
+            names,
+            ranged_weapons,
+            renderers,
+            mut in_backpacks,
+            mut positions,
+            mut wants_to_shoots,
+        ) = data;
+
+        for (entity, wants_to_shoot) in (&entities, &wants_to_shoots).join() {
+            let (shooter_position, target_position) = match (positions.get(entity), positions.get(wants_to_shoot.target)) {
+                (Some(shooter), Some(target)) => (Point::new(shooter.x, shooter.y), Point::new(target.x, target.y)),
+                _ => continue,
+            };
+            let ranged_weapon = match ranged_weapons.get(wants_to_shoot.weapon) {
+                None => continue,
+                Some(ranged_weapon) => ranged_weapon,
+            };
+            let shooter_name = &names.get(entity).unwrap().name;
+            let ammo_name = &names.get(wants_to_shoot.ammo).unwrap().name;
+
+            // The shot hits whoever is in the way first; anything else blocking the way stops it short, like a wall.
+            let mut path = map.projectile_path(shooter_position, target_position);
+            let mut hit_target: Option<Entity> = None;
+            if let Some(last_point) = path.last() {
+                let index = map.xy_idx(last_point.x, last_point.y);
+                if map.blocked_tiles[index] {
+                    hit_target = map.tile_contents[index].iter().find(|target| combat_stats.contains(**target)).copied();
+                    if hit_target.is_none() {
+                        path.pop();
+                    }
+                }
+            }
+            let landing_point = *path.last().unwrap_or(&shooter_position);
+            let is_visible = map.visible_tiles[map.xy_idx(shooter_position.x, shooter_position.y)]
+                || map.visible_tiles[map.xy_idx(landing_point.x, landing_point.y)];
+
+            match hit_target {
+                None => {
+                    if is_visible {
+                        game_log.entries.push(format!("{}'s {} misses.", shooter_name, ammo_name));
+                    }
+                }
+                Some(hit_target) => {
+                    // The effects queue applies the damage and logs the hit, like any other source of damage.
+                    let damage = i32::max(0, ranged_weapon.damage - combat_stats.get(hit_target).unwrap().defense);
+                    let source = format!("{}'s {}", shooter_name, ammo_name);
+                    effect_queue.add(Some(entity), &source, EffectType::Damage { amount: damage }, Targets::Entity { target: hit_target });
+                }
+            }
+
+            // Ammunition that survives lands where the shot stopped, to be picked up again.
+            in_backpacks.remove(wants_to_shoot.ammo);
+            if rng.range(0, 100) < AMMO_RECOVERY_CHANCE {
+                positions.insert(wants_to_shoot.ammo, Position { x: landing_point.x, y: landing_point.y })
+                    .expect("Unable to insert position");
+            } else {
+                entities.delete(wants_to_shoot.ammo).expect("Delete Entity Failed.");
+            }
+            projectile_animation.start(path, renderers.get(wants_to_shoot.ammo));
+        }
+
+        // Clear all WantsToShoot components for next tick.
+        wants_to_shoots.clear();
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use specs::prelude::*;
+use super::{GameLog, ItemIdentification};
+
+mod raw_master;
+mod raw_structs;
+
+pub use raw_master::*;
+pub use raw_structs::*;
+
+/// Where the raws live, relative to the crate root.
+pub const RAWS_PATH: &str = "raws/spawns.json";
+
+// Built into the binary, so the game runs no matter which directory it's started from.
+const EMBEDDED_RAWS: &str = include_str!("../../raws/spawns.json");
+
+// How often to check whether the raws file has been edited.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps track of the raws file on disk, so edits can be picked up while the game is running.
+pub struct RawsWatcher {
+    last_modified: Option<SystemTime>,
+    last_polled: Instant,
+}
+
+impl RawsWatcher {
+    // Constructor
+    pub fn new() -> RawsWatcher {
+        RawsWatcher {
+            last_modified: last_modified(),
+            last_polled: Instant::now(),
+        }
+    }
+}
+
+impl Default for RawsWatcher {
+    fn default() -> Self {
+        RawsWatcher::new()
+    }
+}
+
+fn last_modified() -> Option<SystemTime> {
+    fs::metadata(RAWS_PATH).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Loads the raws file from disk if there is one, or the raws built into the game otherwise.
+/// The game can't run without them, so any problem is fatal.
+pub fn load_raws() -> RawMaster {
+    let json = fs::read_to_string(RAWS_PATH).unwrap_or_else(|_| EMBEDDED_RAWS.to_string());
+    match RawMaster::from_json(&json) {
+        Ok(raw_master) => raw_master,
+        Err(errors) => panic!("Unable to load {}:\n{}", RAWS_PATH, errors.join("\n")),
+    }
+}
+
+/// Reloads the raws file from disk and applies the new values to everything already spawned.
+/// Problems are reported in the GameLog, and the current raws are kept.
+pub fn reload_raws(ecs: &mut World) {
+    ecs.write_resource::<RawsWatcher>().last_modified = last_modified();
+
+    let result = fs::read_to_string(RAWS_PATH)
+        .map_err(|error| vec![error.to_string()])
+        .and_then(|json| RawMaster::from_json(&json));
+    match result {
+        Ok(raw_master) => {
+            ecs.insert(raw_master);
+            update_spawned_entities(ecs);
+            ecs.write_resource::<ItemIdentification>().add_missing_names(
+                &ecs.fetch::<RawMaster>(),
+                &mut ecs.write_resource::<rltk::RandomNumberGenerator>(),
+            );
+            ecs.write_resource::<GameLog>().entries.push(format!("Reloaded {}.", RAWS_PATH));
+        }
+        Err(errors) => {
+            let mut game_log = ecs.write_resource::<GameLog>();
+            game_log.entries.push(format!("Unable to reload {}:", RAWS_PATH));
+            for error in errors {
+                game_log.entries.push(error);
+            }
+        }
+    }
+}
+
+/// Reloads the raws if the file has been modified since it was last loaded.
+pub fn poll_raws_file(ecs: &mut World) {
+    {
+        let mut raws_watcher = ecs.write_resource::<RawsWatcher>();
+        if raws_watcher.last_polled.elapsed() < POLL_INTERVAL { return; }
+        raws_watcher.last_polled = Instant::now();
+
+        let modified = last_modified();
+        if modified.is_none() || modified == raws_watcher.last_modified { return; }
+    }
+    reload_raws(ecs);
+}
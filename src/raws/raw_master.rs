@@ -0,0 +1,901 @@
+use std::collections::HashMap;
+
+use rltk::{RandomNumberGenerator, RGB};
+use specs::prelude::*;
+use specs::saveload::{MarkedBuilder, SimpleMarker};
+use crate::{
+    Ability,
+    AreaOfEffect,
+    Barks,
+    BlocksTile,
+    Charges,
+    Charms,
+    CombatStats,
+    Confusion,
+    Consumable,
+    Container,
+    Cursed,
+    DetectItems,
+    DetectMonsters,
+    Faction,
+    Identifies,
+    InflictsDamage,
+    Item,
+    KnownSpell,
+    KeepsDistance,
+    LeavesCorpse,
+    Locked,
+    LootTable,
+    MagicMapper,
+    Monster,
+    Name,
+    Peaceful,
+    Position,
+    ProvidesFood,
+    ProvidesHealing,
+    RandomTable,
+    Ranged,
+    RangedWeapon,
+    Recharges,
+    RemovesCurse,
+    Renderer,
+    SerializeMe,
+    Shatters,
+    Stackable,
+    Summons,
+    TeachesSpell,
+    Teleports,
+    UsesItems,
+    Value,
+    Viewshed,
+    Wanders,
+    Weight,
+};
+use super::{RawEffects, RawItem, RawMob, RawProp, RawRenderer, RawShops, RawSpell, RawTown, RawUnidentified, Raws};
+
+/// Names that spawn and loot tables may use without defining them in the raws.
+/// "None" spawns nothing, and "Gold" is spawned in code since its amount depends on the depth.
+pub const BUILT_IN_SPAWNS: [&str; 2] = ["None", "Gold"];
+
+/// The parsed raws, indexed by name.
+pub struct RawMaster {
+    raws: Raws,
+    item_index: HashMap<String, usize>,
+    mob_index: HashMap<String, usize>,
+    prop_index: HashMap<String, usize>,
+    loot_table_index: HashMap<String, usize>,
+    spell_index: HashMap<String, usize>,
+}
+
+// A copy of one definition, so the World isn't borrowed while the entity is built.
+enum Template {
+    Item(RawItem),
+    Mob(RawMob),
+    Prop(RawProp),
+}
+
+impl RawMaster {
+    /// Parses and validates a raws file, returning every problem found.
+    pub fn from_json(json: &str) -> Result<RawMaster, Vec<String>> {
+        let raws: Raws = serde_json::from_str(json).map_err(|error| vec![error.to_string()])?;
+        RawMaster::new(raws)
+    }
+
+    /// Indexes and validates already parsed raws, returning every problem found.
+    pub fn new(raws: Raws) -> Result<RawMaster, Vec<String>> {
+        let mut errors: Vec<String> = Vec::new();
+
+        // Items, mobs and props are all spawned by name, so they share one namespace.
+        let mut spawnable_names: HashMap<String, &str> = HashMap::new();
+        let item_index = index_names("item", raws.items.iter().map(|item| &item.name), &mut spawnable_names, &mut errors);
+        let mob_index = index_names("mob", raws.mobs.iter().map(|mob| &mob.name), &mut spawnable_names, &mut errors);
+        let prop_index = index_names("prop", raws.props.iter().map(|prop| &prop.name), &mut spawnable_names, &mut errors);
+        let loot_table_index = index_names(
+            "loot table",
+            raws.loot_tables.iter().map(|loot_table| &loot_table.name),
+            &mut HashMap::new(),
+            &mut errors,
+        );
+        let spell_index = index_names("spell", raws.spells.iter().map(|spell| &spell.name), &mut HashMap::new(), &mut errors);
+        let is_spawnable = |name: &str| spawnable_names.contains_key(name) || BUILT_IN_SPAWNS.contains(&name);
+
+        for item in raws.items.iter() {
+            let owner = format!("item \"{}\"", item.name);
+            validate_renderer(&owner, &item.renderer, &mut errors);
+            validate_effects(&owner, &item.effects, &mob_index, &spell_index, &mut errors);
+            if item.charges.is_some_and(|charges| charges < 1) {
+                errors.push(format!("{}: charges must be at least 1", owner));
+            }
+            if item.stackable && item.charges.is_some() {
+                errors.push(format!("{}: items with charges can't be stackable", owner));
+            }
+            if item.weight < 0.0 {
+                errors.push(format!("{}: weight can't be negative", owner));
+            }
+            if item.curse_chance.is_some_and(|curse_chance| !(0..=100).contains(&curse_chance)) {
+                errors.push(format!("{}: curse_chance must be between 0 and 100", owner));
+            }
+            if item.value.is_some_and(|value| value < 1) {
+                errors.push(format!("{}: value must be at least 1 gold", owner));
+            }
+            if let Some(quantity) = item.quantity {
+                if !item.stackable {
+                    errors.push(format!("{}: only stackable items can spawn more than one at a time", owner));
+                }
+                if quantity < 1 {
+                    errors.push(format!("{}: quantity must be at least 1", owner));
+                }
+            }
+            if let Some(ranged_weapon) = &item.ranged_weapon {
+                if !item_index.contains_key(&ranged_weapon.ammo) {
+                    errors.push(format!("{}: fires unknown item \"{}\"", owner, ranged_weapon.ammo));
+                }
+                if ranged_weapon.range < 1 {
+                    errors.push(format!("{}: range must be at least 1", owner));
+                }
+            }
+        }
+
+        for mob in raws.mobs.iter() {
+            let owner = format!("mob \"{}\"", mob.name);
+            validate_renderer(&owner, &mob.renderer, &mut errors);
+            if let Some(loot_table) = &mob.loot_table {
+                if !loot_table_index.contains_key(loot_table) {
+                    errors.push(format!("{}: unknown loot table \"{}\"", owner, loot_table));
+                }
+            }
+            for ability in mob.abilities.iter() {
+                let ability_owner = format!("{}, ability \"{}\"", owner, ability.name);
+                if ability.effects.ranged.is_some() {
+                    errors.push(format!("{}: abilities set their \"range\" instead of a \"ranged\" effect", ability_owner));
+                }
+                validate_effects(&ability_owner, &ability.effects, &mob_index, &spell_index, &mut errors);
+            }
+        }
+
+        for spell in raws.spells.iter() {
+            let owner = format!("spell \"{}\"", spell.name);
+            if spell.effects.ranged.is_some() {
+                errors.push(format!("{}: spells set their \"range\" instead of a \"ranged\" effect", owner));
+            }
+            if spell.range.is_some_and(|range| range < 1) {
+                errors.push(format!("{}: range must be at least 1", owner));
+            }
+            if spell.mana_cost < 0 {
+                errors.push(format!("{}: mana_cost can't be negative", owner));
+            }
+            validate_effects(&owner, &spell.effects, &mob_index, &spell_index, &mut errors);
+        }
+
+        for prop in raws.props.iter() {
+            let owner = format!("prop \"{}\"", prop.name);
+            validate_renderer(&owner, &prop.renderer, &mut errors);
+            if let Some(container) = &prop.container {
+                if !loot_table_index.contains_key(&container.loot_table) {
+                    errors.push(format!("{}: unknown loot table \"{}\"", owner, container.loot_table));
+                }
+                if container.rolls < 1 {
+                    errors.push(format!("{}: rolls must be at least 1", owner));
+                }
+                if !(0..=100).contains(&container.locked_chance) {
+                    errors.push(format!("{}: locked_chance must be between 0 and 100", owner));
+                }
+                match &container.key {
+                    None if container.locked_chance > 0 => {
+                        errors.push(format!("{}: containers that can be locked need a key", owner));
+                    }
+                    Some(key) if !item_index.contains_key(key) => {
+                        errors.push(format!("{}: key \"{}\" is not an item", owner, key));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(shops) = &raws.shops {
+            if !mob_index.contains_key(&shops.shopkeeper) {
+                errors.push(format!("shops: shopkeeper \"{}\" is not a mob", shops.shopkeeper));
+            }
+            if !(0..=100).contains(&shops.chance) {
+                errors.push("shops: chance must be between 0 and 100".to_string());
+            }
+            if shops.stock_size < 1 {
+                errors.push("shops: stock_size must be at least 1".to_string());
+            }
+        }
+
+        if let Some(town) = &raws.town {
+            if town.shop_count < 0 {
+                errors.push("town: shop_count can't be negative".to_string());
+            }
+            if town.shop_count > 0 && raws.shops.is_none() {
+                errors.push("town: shops need a \"shops\" entry to say who runs them".to_string());
+            }
+            for townsfolk in town.townsfolk.iter() {
+                if !mob_index.contains_key(&townsfolk.name) {
+                    errors.push(format!("town: townsfolk \"{}\" is not a mob", townsfolk.name));
+                }
+                if townsfolk.count < 1 {
+                    errors.push(format!("town: townsfolk \"{}\" count must be at least 1", townsfolk.name));
+                }
+            }
+        }
+
+        let mut spawn_table_names: Vec<&str> = Vec::new();
+        for entry in raws.spawn_table.iter() {
+            let owner = format!("spawn table \"{}\"", entry.name);
+            if !is_spawnable(&entry.name) {
+                errors.push(format!("{}: not an item, mob or prop", owner));
+            }
+            if spawn_table_names.contains(&entry.name.as_str()) {
+                errors.push(format!("{}: listed more than once", owner));
+            }
+            spawn_table_names.push(&entry.name);
+            validate_depths(&owner, entry.min_depth, entry.max_depth, &mut errors);
+            if entry.cost() < 1 {
+                errors.push(format!("{}: cost must be at least 1", owner));
+            }
+        }
+
+        for guaranteed_spawn in raws.guaranteed_spawns.iter() {
+            let owner = format!("guaranteed spawn \"{}\"", guaranteed_spawn.name);
+            if !is_spawnable(&guaranteed_spawn.name) {
+                errors.push(format!("{}: not an item, mob or prop", owner));
+            }
+            validate_depths(&owner, guaranteed_spawn.min_depth, guaranteed_spawn.max_depth, &mut errors);
+        }
+
+        for loot_table in raws.loot_tables.iter() {
+            let owner = format!("loot table \"{}\"", loot_table.name);
+            for drop in loot_table.drops.iter() {
+                match (&drop.name, &drop.table) {
+                    (Some(name), None) => {
+                        if !is_spawnable(name) {
+                            errors.push(format!("{}: \"{}\" is not an item, mob or prop", owner, name));
+                        }
+                    }
+                    (None, Some(table)) => {
+                        if !loot_table_index.contains_key(table) {
+                            errors.push(format!("{}: unknown loot table \"{}\"", owner, table));
+                        }
+                    }
+                    _ => errors.push(format!("{}: each drop needs either a \"name\" or a \"table\"", owner)),
+                }
+            }
+            if refers_back_to(&raws, &loot_table_index, &loot_table.name, &loot_table.name, &mut Vec::new()) {
+                errors.push(format!("{}: rolls on itself through its nested tables", owner));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(RawMaster {
+            raws,
+            item_index,
+            mob_index,
+            prop_index,
+            loot_table_index,
+            spell_index,
+        })
+    }
+
+    /// The table rooms are filled from at the given depth.
+    /// Entries that can't spawn at this depth are left out.
+    pub fn spawn_table(&self, map_depth: i32) -> RandomTable {
+        let mut table = RandomTable::new();
+        for (name, weight) in self.spawn_weights(map_depth) {
+            table = table.add(name, weight);
+        }
+        table
+    }
+
+    /// The table shops at the given depth are stocked from: the items in the spawn table that have a value,
+    /// as likely as they are to spawn there.
+    pub fn shop_table(&self, map_depth: i32) -> RandomTable {
+        let mut table = RandomTable::new();
+        for (name, weight) in self.spawn_weights(map_depth) {
+            if self.item(name).is_some_and(|item| item.value.is_some()) {
+                table = table.add(name, weight);
+            }
+        }
+        table
+    }
+
+    /// How shops are spawned, if the raws have any.
+    pub fn shops(&self) -> Option<&RawShops> {
+        self.raws.shops.as_ref()
+    }
+
+    /// Who lives in the town, if the raws say.
+    pub fn town(&self) -> Option<&RawTown> {
+        self.raws.town.as_ref()
+    }
+
+    /// The names in the spawn table, in the order the raws list them.
+    pub fn spawn_table_names(&self) -> Vec<&str> {
+        self.raws.spawn_table.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    /// The names of every loot table, in the order the raws list them.
+    pub fn loot_table_names(&self) -> Vec<&str> {
+        self.raws.loot_tables.iter().map(|loot_table| loot_table.name.as_str()).collect()
+    }
+
+    /// The names of everything that's guaranteed to spawn at some depth.
+    pub fn guaranteed_spawn_names(&self) -> Vec<&str> {
+        self.raws.guaranteed_spawns.iter().map(|guaranteed_spawn| guaranteed_spawn.name.as_str()).collect()
+    }
+
+    /// Every spawn table entry that can spawn at the given depth, with its weight there.
+    pub fn spawn_weights(&self, map_depth: i32) -> Vec<(&str, i32)> {
+        self.raws.spawn_table
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.weight_at(map_depth)))
+            .filter(|(_name, weight)| *weight > 0)
+            .collect()
+    }
+
+    /// How much of the level's spawn budget spawning "name" from the spawn table uses up.
+    pub fn spawn_cost(&self, name: &str) -> i32 {
+        match self.raws.spawn_table.iter().find(|entry| entry.name == name) {
+            None => 1,
+            Some(entry) => entry.cost(),
+        }
+    }
+
+    /// The total cost of everything the spawn table may place on a level at the given depth.
+    pub fn spawn_budget(&self, map_depth: i32) -> i32 {
+        self.raws.spawn_budget.base + self.raws.spawn_budget.per_depth * map_depth
+    }
+
+    /// What every level at the given depth gets regardless of the spawn table, and how many of each.
+    pub fn guaranteed_spawns(&self, map_depth: i32) -> Vec<(String, i32)> {
+        self.raws.guaranteed_spawns
+            .iter()
+            .map(|guaranteed_spawn| (guaranteed_spawn.name.clone(), guaranteed_spawn.count_at(map_depth)))
+            .filter(|(_name, count)| *count > 0)
+            .collect()
+    }
+
+    /// The named loot table, with any nested tables it rolls on. Unknown tables never drop anything.
+    pub fn loot_table(&self, table_name: &str) -> RandomTable {
+        let mut table = RandomTable::new();
+        if let Some(index) = self.loot_table_index.get(table_name) {
+            for drop in self.raws.loot_tables[*index].drops.iter() {
+                // Validation guarantees exactly one of these is set, and that tables don't nest in a loop.
+                if let Some(name) = &drop.name {
+                    table = table.add(name, drop.weight);
+                }
+                if let Some(nested_table_name) = &drop.table {
+                    table = table.add_table(self.loot_table(nested_table_name), drop.weight);
+                }
+            }
+        }
+        table
+    }
+
+    /// The loot table the named container prop is filled from, and how many times to roll it.
+    pub fn container_loot(&self, name: &str) -> Option<(&str, i32)> {
+        let prop = &self.raws.props[*self.prop_index.get(name)?];
+        prop.container.as_ref().map(|container| (container.loot_table.as_str(), container.rolls))
+    }
+
+    /// Every item that has to be identified before the player knows what it is, and what kind it is.
+    pub fn unidentified_items(&self) -> Vec<(&str, RawUnidentified)> {
+        self.raws.items
+            .iter()
+            .filter_map(|item| item.unidentified.map(|kind| (item.name.as_str(), kind)))
+            .collect()
+    }
+
+    /// The spells the player knows at the start of a new game.
+    pub fn starting_spell_names(&self) -> Vec<String> {
+        self.raws.spells.iter().filter(|spell| spell.starting).map(|spell| spell.name.clone()).collect()
+    }
+
+    fn item(&self, name: &str) -> Option<&RawItem> {
+        self.item_index.get(name).map(|index| &self.raws.items[*index])
+    }
+
+    fn mob(&self, name: &str) -> Option<&RawMob> {
+        self.mob_index.get(name).map(|index| &self.raws.mobs[*index])
+    }
+
+    fn spell(&self, name: &str) -> Option<&RawSpell> {
+        self.spell_index.get(name).map(|index| &self.raws.spells[*index])
+    }
+
+    fn template(&self, name: &str) -> Option<Template> {
+        if let Some(index) = self.item_index.get(name) {
+            return Some(Template::Item(self.raws.items[*index].clone()));
+        }
+        if let Some(index) = self.mob_index.get(name) {
+            return Some(Template::Mob(self.raws.mobs[*index].clone()));
+        }
+        if let Some(index) = self.prop_index.get(name) {
+            return Some(Template::Prop(self.raws.props[*index].clone()));
+        }
+        None
+    }
+}
+
+// Maps each name to its position in the list, reporting names that were already taken.
+fn index_names<'a>(
+    kind: &'static str,
+    names: impl Iterator<Item=&'a String>,
+    taken_names: &mut HashMap<String, &'static str>,
+    errors: &mut Vec<String>,
+) -> HashMap<String, usize> {
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (position, name) in names.enumerate() {
+        if let Some(other_kind) = taken_names.get(name) {
+            errors.push(format!("{} \"{}\": the name is already used by another {}", kind, name, other_kind));
+            continue;
+        }
+        taken_names.insert(name.clone(), kind);
+        index.insert(name.clone(), position);
+    }
+    index
+}
+
+fn validate_renderer(owner: &str, renderer: &RawRenderer, errors: &mut Vec<String>) {
+    if renderer.glyph.chars().count() != 1 {
+        errors.push(format!("{}: glyph \"{}\" must be exactly one character", owner, renderer.glyph));
+    }
+    for color in [&renderer.fg, &renderer.bg] {
+        if RGB::from_hex(color).is_err() {
+            errors.push(format!("{}: \"{}\" is not a color like \"#FF0000\"", owner, color));
+        }
+    }
+}
+
+// Whether rolling on "table_name" can end up rolling on "start" again.
+fn refers_back_to(
+    raws: &Raws,
+    loot_table_index: &HashMap<String, usize>,
+    start: &str,
+    table_name: &str,
+    visited: &mut Vec<String>,
+) -> bool {
+    let index = match loot_table_index.get(table_name) {
+        None => return false,
+        Some(index) => *index,
+    };
+    for drop in raws.loot_tables[index].drops.iter() {
+        if let Some(nested_table_name) = &drop.table {
+            if nested_table_name == start { return true; }
+            if visited.contains(nested_table_name) { continue; }
+            visited.push(nested_table_name.clone());
+            if refers_back_to(raws, loot_table_index, start, nested_table_name, visited) { return true; }
+        }
+    }
+    false
+}
+
+fn validate_depths(owner: &str, min_depth: Option<i32>, max_depth: Option<i32>, errors: &mut Vec<String>) {
+    if let (Some(min_depth), Some(max_depth)) = (min_depth, max_depth) {
+        if min_depth > max_depth {
+            errors.push(format!("{}: min_depth {} is deeper than max_depth {}", owner, min_depth, max_depth));
+        }
+    }
+}
+
+fn validate_effects(
+    owner: &str,
+    effects: &RawEffects,
+    mob_index: &HashMap<String, usize>,
+    spell_index: &HashMap<String, usize>,
+    errors: &mut Vec<String>,
+) {
+    if let Some(summons) = &effects.summons {
+        if !mob_index.contains_key(summons) {
+            errors.push(format!("{}: summons unknown mob \"{}\"", owner, summons));
+        }
+    }
+    if let Some(spell) = &effects.teaches_spell {
+        if !spell_index.contains_key(spell) {
+            errors.push(format!("{}: teaches unknown spell \"{}\"", owner, spell));
+        }
+    }
+    for turns in [effects.detect_items, effects.detect_monsters].into_iter().flatten() {
+        if turns < 1 {
+            errors.push(format!("{}: detection must last at least 1 turn", owner));
+        }
+    }
+}
+
+fn renderer(raw_renderer: &RawRenderer) -> Renderer {
+    // Glyphs and colors were checked when the raws were loaded.
+    Renderer {
+        glyph: rltk::to_cp437(raw_renderer.glyph.chars().next().unwrap()),
+        fg: RGB::from_hex(&raw_renderer.fg).expect("Invalid color in raws."),
+        bg: RGB::from_hex(&raw_renderer.bg).expect("Invalid color in raws."),
+        render_order: raw_renderer.order,
+    }
+}
+
+// Every effect component, so an existing entity can be brought in line with its RawEffects.
+struct EffectStorages<'a> {
+    area_of_effect: WriteStorage<'a, AreaOfEffect>,
+    charms: WriteStorage<'a, Charms>,
+    confusion: WriteStorage<'a, Confusion>,
+    detect_items: WriteStorage<'a, DetectItems>,
+    detect_monsters: WriteStorage<'a, DetectMonsters>,
+    identifies: WriteStorage<'a, Identifies>,
+    inflicts_damage: WriteStorage<'a, InflictsDamage>,
+    magic_mapper: WriteStorage<'a, MagicMapper>,
+    provides_food: WriteStorage<'a, ProvidesFood>,
+    provides_healing: WriteStorage<'a, ProvidesHealing>,
+    ranged: WriteStorage<'a, Ranged>,
+    recharges: WriteStorage<'a, Recharges>,
+    removes_curse: WriteStorage<'a, RemovesCurse>,
+    summons: WriteStorage<'a, Summons>,
+    teaches_spell: WriteStorage<'a, TeachesSpell>,
+    teleports: WriteStorage<'a, Teleports>,
+}
+
+impl<'a> EffectStorages<'a> {
+    fn fetch(ecs: &'a World) -> EffectStorages<'a> {
+        EffectStorages {
+            area_of_effect: ecs.write_storage::<AreaOfEffect>(),
+            charms: ecs.write_storage::<Charms>(),
+            confusion: ecs.write_storage::<Confusion>(),
+            detect_items: ecs.write_storage::<DetectItems>(),
+            detect_monsters: ecs.write_storage::<DetectMonsters>(),
+            identifies: ecs.write_storage::<Identifies>(),
+            inflicts_damage: ecs.write_storage::<InflictsDamage>(),
+            magic_mapper: ecs.write_storage::<MagicMapper>(),
+            provides_food: ecs.write_storage::<ProvidesFood>(),
+            provides_healing: ecs.write_storage::<ProvidesHealing>(),
+            ranged: ecs.write_storage::<Ranged>(),
+            recharges: ecs.write_storage::<Recharges>(),
+            removes_curse: ecs.write_storage::<RemovesCurse>(),
+            summons: ecs.write_storage::<Summons>(),
+            teaches_spell: ecs.write_storage::<TeachesSpell>(),
+            teleports: ecs.write_storage::<Teleports>(),
+        }
+    }
+
+    // Adds, updates or removes each effect component to match the raws.
+    fn apply(&mut self, entity: Entity, effects: &RawEffects) {
+        set_component(&mut self.area_of_effect, entity, effects.area_of_effect.map(|radius| AreaOfEffect { radius }));
+        set_component(&mut self.charms, entity, if effects.charms { Some(Charms {}) } else { None });
+        set_component(&mut self.confusion, entity, effects.confusion.map(|turns| Confusion { turns }));
+        set_component(&mut self.detect_items, entity, effects.detect_items.map(|turns| DetectItems { turns }));
+        set_component(&mut self.detect_monsters, entity, effects.detect_monsters.map(|turns| DetectMonsters { turns }));
+        set_component(&mut self.identifies, entity, if effects.identifies { Some(Identifies {}) } else { None });
+        set_component(&mut self.inflicts_damage, entity, effects.inflicts_damage.map(|damage| InflictsDamage { damage }));
+        set_component(&mut self.magic_mapper, entity, if effects.magic_mapping { Some(MagicMapper {}) } else { None });
+        set_component(&mut self.provides_food, entity, if effects.provides_food { Some(ProvidesFood {}) } else { None });
+        set_component(&mut self.provides_healing, entity, effects.provides_healing.map(|heal_amount| ProvidesHealing { heal_amount }));
+        set_component(&mut self.ranged, entity, effects.ranged.map(|range| Ranged { range }));
+        set_component(&mut self.recharges, entity, if effects.recharges { Some(Recharges {}) } else { None });
+        set_component(&mut self.removes_curse, entity, if effects.removes_curse { Some(RemovesCurse {}) } else { None });
+        set_component(&mut self.summons, entity, effects.summons.clone().map(|name| Summons { name }));
+        set_component(&mut self.teaches_spell, entity, effects.teaches_spell.clone().map(|name| TeachesSpell { name }));
+        set_component(&mut self.teleports, entity, if effects.teleports { Some(Teleports {}) } else { None });
+    }
+}
+
+fn set_component<T: Component>(storage: &mut WriteStorage<T>, entity: Entity, component: Option<T>) {
+    match component {
+        None => { storage.remove(entity); }
+        Some(component) => { storage.insert(entity, component).expect("Unable to insert effect component."); }
+    }
+}
+
+/// Applies the current raws to everything spawned from an older version of them,
+/// so balance changes take effect without starting a new game.
+pub fn update_spawned_entities(ecs: &mut World) {
+    let raw_master = ecs.fetch::<RawMaster>();
+    let entities = ecs.entities();
+    let items = ecs.read_storage::<Item>();
+    let monsters = ecs.read_storage::<Monster>();
+    let names = ecs.read_storage::<Name>();
+    let mut abilities = ecs.write_storage::<Ability>();
+    let mut known_spells = ecs.write_storage::<KnownSpell>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let mut effect_storages = EffectStorages::fetch(ecs);
+    let mut ranged_weapons = ecs.write_storage::<RangedWeapon>();
+    let mut shatters_components = ecs.write_storage::<Shatters>();
+    let mut values = ecs.write_storage::<Value>();
+    let mut weights = ecs.write_storage::<Weight>();
+    let mut charges_components = ecs.write_storage::<Charges>();
+
+    // Monsters keep the damage they've taken, but never die from a change to their max hp.
+    for (_monster, name, combat_stat) in (&monsters, &names, &mut combat_stats).join() {
+        if let Some(mob) = raw_master.mob(&name.name) {
+            let damage_taken = combat_stat.max_hp - combat_stat.hp;
+            combat_stat.max_hp = mob.stats.max_hp;
+            combat_stat.hp = i32::max(1, mob.stats.max_hp - damage_taken);
+            combat_stat.defense = mob.stats.defense;
+            combat_stat.power = mob.stats.power;
+        }
+    }
+
+    for (entity, _item, name) in (&entities, &items, &names).join() {
+        if let Some(item) = raw_master.item(&name.name) {
+            effect_storages.apply(entity, &item.effects);
+            let ranged_weapon = item.ranged_weapon.as_ref().map(|ranged_weapon| RangedWeapon {
+                ammo: ranged_weapon.ammo.clone(),
+                damage: ranged_weapon.damage,
+                range: ranged_weapon.range,
+            });
+            set_component(&mut ranged_weapons, entity, ranged_weapon);
+            set_component(&mut shatters_components, entity, if item.shatters { Some(Shatters {}) } else { None });
+            set_component(&mut values, entity, item.value.map(|gold| Value { gold }));
+            set_component(&mut weights, entity, if item.weight > 0.0 { Some(Weight { pounds: item.weight }) } else { None });
+            // Items keep the charges they have left, up to the new maximum.
+            let charges = item.charges.map(|max| Charges {
+                current: charges_components.get(entity).map_or(max, |charges| i32::min(charges.current, max)),
+                max,
+            });
+            set_component(&mut charges_components, entity, charges);
+        }
+    }
+
+    for (entity, ability, name) in (&entities, &mut abilities, &names).join() {
+        let raw_ability = names
+            .get(ability.owner)
+            .and_then(|owner_name| raw_master.mob(&owner_name.name))
+            .and_then(|mob| mob.abilities.iter().find(|raw_ability| raw_ability.name == name.name));
+        if let Some(raw_ability) = raw_ability {
+            ability.cooldown = raw_ability.cooldown;
+            ability.turns_until_ready = i32::min(ability.turns_until_ready, raw_ability.cooldown);
+            effect_storages.apply(entity, &raw_ability.effects);
+            effect_storages.ranged.insert(entity, Ranged { range: raw_ability.range })
+                .expect("Unable to insert Ranged component.");
+        }
+    }
+
+    for (entity, known_spell, name) in (&entities, &mut known_spells, &names).join() {
+        if let Some(spell) = raw_master.spell(&name.name) {
+            known_spell.mana_cost = spell.mana_cost;
+            effect_storages.apply(entity, &spell.effects);
+            set_component(&mut effect_storages.ranged, entity, spell.range.map(|range| Ranged { range }));
+        }
+    }
+}
+
+fn with_effects<'a>(mut builder: EntityBuilder<'a>, effects: &RawEffects) -> EntityBuilder<'a> {
+    if let Some(radius) = effects.area_of_effect {
+        builder = builder.with(AreaOfEffect { radius });
+    }
+    if effects.charms {
+        builder = builder.with(Charms {});
+    }
+    if let Some(turns) = effects.confusion {
+        builder = builder.with(Confusion { turns });
+    }
+    if let Some(turns) = effects.detect_items {
+        builder = builder.with(DetectItems { turns });
+    }
+    if let Some(turns) = effects.detect_monsters {
+        builder = builder.with(DetectMonsters { turns });
+    }
+    if effects.identifies {
+        builder = builder.with(Identifies {});
+    }
+    if let Some(damage) = effects.inflicts_damage {
+        builder = builder.with(InflictsDamage { damage });
+    }
+    if effects.magic_mapping {
+        builder = builder.with(MagicMapper {});
+    }
+    if effects.provides_food {
+        builder = builder.with(ProvidesFood {});
+    }
+    if let Some(heal_amount) = effects.provides_healing {
+        builder = builder.with(ProvidesHealing { heal_amount });
+    }
+    if let Some(range) = effects.ranged {
+        builder = builder.with(Ranged { range });
+    }
+    if effects.recharges {
+        builder = builder.with(Recharges {});
+    }
+    if effects.removes_curse {
+        builder = builder.with(RemovesCurse {});
+    }
+    if let Some(name) = &effects.summons {
+        builder = builder.with(Summons { name: name.clone() });
+    }
+    if let Some(name) = &effects.teaches_spell {
+        builder = builder.with(TeachesSpell { name: name.clone() });
+    }
+    if effects.teleports {
+        builder = builder.with(Teleports {});
+    }
+    builder
+}
+
+/// Spawns the item, mob or prop with the given name from the raws.
+/// Returns None if there is no such definition.
+pub fn spawn_named_entity(ecs: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    let template = ecs.fetch::<RawMaster>().template(name)?;
+    let entity = match template {
+        Template::Item(item) => spawn_item(ecs, &item, x, y),
+        Template::Mob(mob) => spawn_mob(ecs, &mob, x, y),
+        Template::Prop(prop) => spawn_prop(ecs, &prop, x, y),
+    };
+    Some(entity)
+}
+
+/// Teaches "owner" the spell with the given name from the raws, returning the spell's entity.
+/// Returns None if there is no such spell.
+pub fn spawn_spell(ecs: &mut World, name: &str, owner: Entity) -> Option<Entity> {
+    let spell = ecs.fetch::<RawMaster>().spell(name)?.clone();
+    // Spells are entities without a Position, cast through WantsToUseItem just like an Ability.
+    let mut builder = ecs
+        .create_entity()
+        .with(KnownSpell { owner, mana_cost: spell.mana_cost })
+        .with(Name { name: spell.name.clone() });
+    if let Some(range) = spell.range {
+        builder = builder.with(Ranged { range });
+    }
+    let spell_entity = with_effects(builder, &spell.effects)
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+    Some(spell_entity)
+}
+
+/// Spawns one of the named item from the raws, but off the map and with nothing rolled for it:
+/// it isn't cursed, and has no charges or quantity, for the caller to fill in.
+/// Returns None if there is no such item.
+pub fn spawn_bare_item(ecs: &mut World, name: &str) -> Option<Entity> {
+    let item = ecs.fetch::<RawMaster>().item(name)?.clone();
+    Some(item_builder(ecs, &item).build())
+}
+
+fn spawn_item(ecs: &mut World, item: &RawItem, x: i32, y: i32) -> Entity {
+    let is_cursed = match item.curse_chance {
+        None => false,
+        Some(curse_chance) => ecs.write_resource::<RandomNumberGenerator>().range(0, 100) < curse_chance,
+    };
+    let charges = item.charges.map(|max| Charges {
+        current: ecs.write_resource::<RandomNumberGenerator>().range(1, max + 1),
+        max,
+    });
+    let quantity = match item.quantity {
+        None => 1,
+        Some(max_quantity) => ecs.write_resource::<RandomNumberGenerator>().range(1, max_quantity + 1),
+    };
+    let mut builder = item_builder(ecs, item).with(Position { x, y });
+    if is_cursed {
+        builder = builder.with(Cursed { discovered: false });
+    }
+    if let Some(charges) = charges {
+        builder = builder.with(charges);
+    }
+    if item.stackable {
+        builder = builder.with(Stackable { quantity });
+    }
+    builder.build()
+}
+
+// Everything every one of the item has, whatever was rolled for it.
+fn item_builder<'a>(ecs: &'a mut World, item: &RawItem) -> EntityBuilder<'a> {
+    let mut builder = ecs
+        .create_entity()
+        // Components
+        .with(Item {})
+        .with(Name { name: item.name.clone() })
+        .with(renderer(&item.renderer));
+    if item.consumable {
+        builder = builder.with(Consumable {});
+    }
+    if item.shatters {
+        builder = builder.with(Shatters {});
+    }
+    if let Some(ranged_weapon) = &item.ranged_weapon {
+        builder = builder.with(RangedWeapon {
+            ammo: ranged_weapon.ammo.clone(),
+            damage: ranged_weapon.damage,
+            range: ranged_weapon.range,
+        });
+    }
+    if let Some(gold) = item.value {
+        builder = builder.with(Value { gold });
+    }
+    if item.weight > 0.0 {
+        builder = builder.with(Weight { pounds: item.weight });
+    }
+    with_effects(builder, &item.effects)
+        // Markers
+        .marked::<SimpleMarker<SerializeMe>>()
+}
+
+fn spawn_mob(ecs: &mut World, mob: &RawMob, x: i32, y: i32) -> Entity {
+    let mut builder = ecs
+        .create_entity()
+        // Components
+        .with(BlocksTile {})
+        .with(CombatStats {
+            max_hp: mob.stats.max_hp,
+            hp: mob.stats.max_hp,
+            defense: mob.stats.defense,
+            power: mob.stats.power,
+        })
+        .with(Faction { name: mob.faction.clone() })
+        .with(Monster {})
+        .with(Name { name: mob.name.clone() })
+        .with(Position { x, y })
+        .with(renderer(&mob.renderer))
+        .with(Viewshed {
+            visible_tiles: Vec::new(),
+            range: mob.vision_range,
+            dirty: true,
+        });
+    if !mob.barks.is_empty() {
+        builder = builder.with(Barks { lines: mob.barks.clone() });
+    }
+    if let Some(distance) = mob.keeps_distance {
+        builder = builder.with(KeepsDistance { distance });
+    }
+    if mob.leaves_corpse {
+        builder = builder.with(LeavesCorpse {});
+    }
+    if let Some(table) = &mob.loot_table {
+        builder = builder.with(LootTable { table: table.clone() });
+    }
+    if mob.peaceful {
+        builder = builder.with(Peaceful {});
+    }
+    if mob.uses_items {
+        builder = builder.with(UsesItems {});
+    }
+    if mob.wanders {
+        builder = builder.with(Wanders {});
+    }
+    let mob_entity = builder
+        // Markers
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+
+    // Abilities are entities without a Position, used through WantsToUseItem just like a scroll.
+    for ability in mob.abilities.iter() {
+        let builder = ecs
+            .create_entity()
+            .with(Ability {
+                owner: mob_entity,
+                cooldown: ability.cooldown,
+                turns_until_ready: 0,
+            })
+            .with(Name { name: ability.name.clone() })
+            .with(Ranged { range: ability.range });
+        with_effects(builder, &ability.effects)
+            .marked::<SimpleMarker<SerializeMe>>()
+            .build();
+    }
+
+    mob_entity
+}
+
+fn spawn_prop(ecs: &mut World, prop: &RawProp, x: i32, y: i32) -> Entity {
+    let lock = match &prop.container {
+        Some(container) if ecs.write_resource::<RandomNumberGenerator>().range(0, 100) < container.locked_chance => {
+            container.key.clone().map(|key| Locked { key })
+        }
+        _ => None,
+    };
+    let mut builder = ecs
+        .create_entity()
+        // Components
+        .with(Name { name: prop.name.clone() })
+        .with(Position { x, y })
+        .with(renderer(&prop.renderer));
+    if prop.blocks_tile {
+        builder = builder.with(BlocksTile {});
+    }
+    if prop.container.is_some() {
+        builder = builder.with(Container {});
+    }
+    if let Some(lock) = lock {
+        builder = builder.with(lock);
+    }
+    builder
+        // Markers
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
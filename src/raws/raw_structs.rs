@@ -0,0 +1,324 @@
+use serde::Deserialize;
+
+// Every struct here denies unknown fields, so a typo in the raws file (or a component
+// the game doesn't know about) is reported instead of silently ignored.
+
+/// Everything in the raws file.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Raws {
+    pub items: Vec<RawItem>,
+    pub mobs: Vec<RawMob>,
+    pub props: Vec<RawProp>,
+    pub spawn_table: Vec<RawSpawnTableEntry>,
+    #[serde(default)]
+    pub guaranteed_spawns: Vec<RawGuaranteedSpawn>,
+    pub spawn_budget: RawSpawnBudget,
+    pub loot_tables: Vec<RawLootTable>,
+    #[serde(default)]
+    pub spells: Vec<RawSpell>,
+    /// Levels without shops if left out.
+    pub shops: Option<RawShops>,
+    /// The town the game starts in. An empty town if left out.
+    pub town: Option<RawTown>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawRenderer {
+    /// A single character.
+    pub glyph: String,
+    /// Colors are hex strings, e.g. "#FF0000".
+    pub fg: String,
+    pub bg: String,
+    pub order: i32,
+}
+
+/// The effect components shared by items and abilities.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RawEffects {
+    pub area_of_effect: Option<i32>,
+    #[serde(default)]
+    pub charms: bool,
+    pub confusion: Option<i32>,
+    /// Shows the user every item on the level for this many turns.
+    pub detect_items: Option<i32>,
+    /// Shows the user every monster on the level for this many turns.
+    pub detect_monsters: Option<i32>,
+    #[serde(default)]
+    pub identifies: bool,
+    pub inflicts_damage: Option<i32>,
+    #[serde(default)]
+    pub magic_mapping: bool,
+    /// Eating it leaves the player well fed.
+    #[serde(default)]
+    pub provides_food: bool,
+    pub provides_healing: Option<i32>,
+    pub ranged: Option<i32>,
+    #[serde(default)]
+    pub recharges: bool,
+    #[serde(default)]
+    pub removes_curse: bool,
+    pub summons: Option<String>,
+    /// Teaches the user the named spell instead of having an effect of its own.
+    pub teaches_spell: Option<String>,
+    #[serde(default)]
+    pub teleports: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawItem {
+    pub name: String,
+    pub renderer: RawRenderer,
+    #[serde(default)]
+    pub consumable: bool,
+    /// In pounds. Weightless items don't count towards the player's carry capacity.
+    #[serde(default)]
+    pub weight: f32,
+    /// How many times it can be used before it needs recharging. It spawns with between 1 and this many left.
+    pub charges: Option<i32>,
+    /// Whether it breaks when thrown, applying its effects to everything next to where it lands.
+    #[serde(default)]
+    pub shatters: bool,
+    /// Whether identical copies are carried as one stack.
+    #[serde(default)]
+    pub stackable: bool,
+    /// Stackable items spawn in a stack of between 1 and this many (e.g. a bundle of arrows).
+    pub quantity: Option<i32>,
+    /// Bows and the like, which shoot the named ammunition from the backpack.
+    pub ranged_weapon: Option<RawRangedWeapon>,
+    /// What the item looks like until the player identifies it. Items without one are always known.
+    pub unidentified: Option<RawUnidentified>,
+    /// The percentage chance of it spawning cursed.
+    pub curse_chance: Option<i32>,
+    /// What a shop charges for it, in gold. Shops don't trade in items without one.
+    pub value: Option<i32>,
+    #[serde(default)]
+    pub effects: RawEffects,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawRangedWeapon {
+    /// The name of the item it shoots.
+    pub ammo: String,
+    pub damage: i32,
+    pub range: i32,
+}
+
+/// The kinds of item that get a random name each game until they're identified.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RawUnidentified {
+    /// e.g. "scroll of ZELGO MER"
+    Scroll,
+    /// e.g. "bubbling violet potion"
+    Potion,
+    /// e.g. "carved oak wand"
+    Wand,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawStats {
+    pub max_hp: i32,
+    pub defense: i32,
+    pub power: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawAbility {
+    pub name: String,
+    pub range: i32,
+    pub cooldown: i32,
+    pub effects: RawEffects,
+}
+
+/// A spell the player can learn and cast for mana, which works just like using an item with its effects.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawSpell {
+    pub name: String,
+    pub mana_cost: i32,
+    /// Spells with a range are cast at a target, like a ranged scroll.
+    pub range: Option<i32>,
+    /// Whether the player knows it from the start of the game.
+    #[serde(default)]
+    pub starting: bool,
+    pub effects: RawEffects,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawMob {
+    pub name: String,
+    pub renderer: RawRenderer,
+    pub faction: String,
+    pub stats: RawStats,
+    pub vision_range: i32,
+    pub keeps_distance: Option<i32>,
+    #[serde(default)]
+    pub wanders: bool,
+    #[serde(default)]
+    pub uses_items: bool,
+    #[serde(default)]
+    pub leaves_corpse: bool,
+    pub loot_table: Option<String>,
+    #[serde(default)]
+    pub abilities: Vec<RawAbility>,
+    /// Things it says now and then, when the player is in sight.
+    #[serde(default)]
+    pub barks: Vec<String>,
+    /// Whether the player swaps places with it instead of attacking it when walking into it.
+    #[serde(default)]
+    pub peaceful: bool,
+}
+
+/// Scenery: things that sit on the map without being picked up or fought.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawProp {
+    pub name: String,
+    pub renderer: RawRenderer,
+    #[serde(default)]
+    pub blocks_tile: bool,
+    /// Set for props that hold items, like chests.
+    pub container: Option<RawContainer>,
+}
+
+/// What a container prop starts out holding, and how it's locked.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawContainer {
+    pub loot_table: String,
+    /// How many times the loot table is rolled to fill it.
+    pub rolls: i32,
+    /// The percentage chance of it being locked.
+    #[serde(default)]
+    pub locked_chance: i32,
+    /// The item that unlocks it. Each key opens one lock.
+    pub key: Option<String>,
+}
+
+/// One entry in the room spawn table. It only spawns between "min_depth" and "max_depth" (inclusive),
+/// with the weight at each depth given by its curve.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawSpawnTableEntry {
+    pub name: String,
+    pub weight: i32,
+    #[serde(default)]
+    pub curve: RawWeightCurve,
+    pub min_depth: Option<i32>,
+    pub max_depth: Option<i32>,
+    /// How much of the level's spawn budget this uses up. Defaults to 1.
+    pub cost: Option<i32>,
+}
+
+impl RawSpawnTableEntry {
+    /// The weight at the given depth, or 0 if it doesn't spawn there.
+    pub fn weight_at(&self, map_depth: i32) -> i32 {
+        if self.min_depth.is_some_and(|min_depth| map_depth < min_depth) { return 0; }
+        if self.max_depth.is_some_and(|max_depth| map_depth > max_depth) { return 0; }
+        let weight = match self.curve {
+            RawWeightCurve::Flat => self.weight,
+            RawWeightCurve::Linear { per_depth } => self.weight + (per_depth * map_depth as f32) as i32,
+            RawWeightCurve::Peak { depth, falloff } => {
+                self.weight - (falloff * (map_depth - depth).abs() as f32) as i32
+            }
+        };
+        i32::max(0, weight)
+    }
+
+    pub fn cost(&self) -> i32 {
+        self.cost.unwrap_or(1)
+    }
+}
+
+/// How a spawn table entry's weight changes with depth. Weights below 0 count as 0.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum RawWeightCurve {
+    /// The same weight at every depth.
+    #[default]
+    Flat,
+    /// "weight + per_depth * depth", rounded down.
+    Linear { per_depth: f32 },
+    /// Most common at "depth", losing "falloff" weight for each level above or below it.
+    Peak { depth: i32, falloff: f32 },
+}
+
+/// Something every level between "min_depth" and "max_depth" gets, whatever the spawn table rolls.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawGuaranteedSpawn {
+    pub name: String,
+    pub count: i32,
+    pub min_depth: Option<i32>,
+    pub max_depth: Option<i32>,
+}
+
+impl RawGuaranteedSpawn {
+    pub fn count_at(&self, map_depth: i32) -> i32 {
+        if self.min_depth.is_some_and(|min_depth| map_depth < min_depth) { return 0; }
+        if self.max_depth.is_some_and(|max_depth| map_depth > max_depth) { return 0; }
+        self.count
+    }
+}
+
+/// The total cost of everything the spawn table may place on one level: "base + per_depth * depth".
+/// Guaranteed spawns don't count towards it.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawSpawnBudget {
+    pub base: i32,
+    pub per_depth: i32,
+}
+
+/// Some levels get a shop: a room with a shopkeeper in it, and nothing else.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawShops {
+    /// The mob that runs each shop.
+    pub shopkeeper: String,
+    /// The percentage chance of a level having a shop.
+    pub chance: i32,
+    /// How many different items each shop is stocked with, rolled from the items in the spawn table at its depth.
+    pub stock_size: i32,
+}
+
+/// Who lives in the town above the dungeon.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawTown {
+    /// How many of the buildings are shops, run and stocked as the "shops" entry describes for depth 1.
+    pub shop_count: i32,
+    /// The mobs out on the streets and in the houses.
+    pub townsfolk: Vec<RawTownsfolk>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawTownsfolk {
+    pub name: String,
+    pub count: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawLootTable {
+    pub name: String,
+    pub drops: Vec<RawLootDrop>,
+}
+
+/// Drops either the named item, mob or prop, or rolls again on another loot table (e.g. "Any Scroll").
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawLootDrop {
+    pub name: Option<String>,
+    pub table: Option<String>,
+    pub weight: i32,
+}
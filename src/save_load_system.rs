@@ -14,6 +14,8 @@ use specs::error::NoError;
 
 use super::components::*;
 use super::map::{Map, MAP_COUNT};
+use super::raws::RawMaster;
+use super::ItemIdentification;
 
 // The short version of what this macro does is that it takes your ECS as the first parameter,
 // and a tuple with your entity store and "markers" stores in it (you'll see this in a moment).
@@ -53,9 +55,10 @@ macro_rules! deserialize_individually {
 pub fn save_game(ecs: &mut World) {
     // Create helper
     let map_copy = ecs.get_mut::<Map>().unwrap().clone();
+    let item_identification_copy = (*ecs.fetch::<ItemIdentification>()).clone();
     let save_helper = ecs
         .create_entity()
-        .with(SerializationHelper { map: map_copy })
+        .with(SerializationHelper { map: map_copy, item_identification: item_identification_copy })
         .marked::<SimpleMarker<SerializeMe>>()
         .build();
     // Perform serialization
@@ -74,28 +77,70 @@ pub fn save_game(ecs: &mut World) {
             serializer,
             data,
             // Components
+            Ability,
             AreaOfEffect,
+            Barks,
             BlocksTile,
+            Charges,
+            Charms,
             CombatStats,
+            Companion,
             Confusion,
             Consumable,
+            Container,
+            Cursed,
+            DetectItems,
+            DetectMonsters,
+            Encumbered,
+            Faction,
+            Gold,
+            HungerClock,
+            Identifies,
             InBackpack,
+            InContainer,
             InflictsDamage,
             Item,
+            KeepsDistance,
+            KnownSpell,
+            LeavesCorpse,
+            Locked,
+            LootTable,
+            MagicMapper,
+            Mana,
             Monster,
             Name,
+            Peaceful,
             Player,
             Position,
+            ProvidesFood,
             ProvidesHealing,
+            Purse,
             Ranged,
+            RangedWeapon,
+            Recharges,
+            RemovesCurse,
             Renderer,
             SerializationHelper,
+            Shatters,
+            Stackable,
             SufferDamage,
+            Summons,
+            TeachesSpell,
+            Teleports,
+            UsesItems,
+            Value,
+            Vendor,
             Viewshed,
+            Wanders,
             WantsToDropItem,
+            WantsToLearnSpell,
             WantsToMelee,
             WantsToPickupItem,
-            WantsToUseItem
+            WantsToShoot,
+            WantsToSummon,
+            WantsToThrowItem,
+            WantsToUseItem,
+            Weight
         );
     }
 
@@ -135,28 +180,70 @@ pub fn load_game(ecs: &mut World) {
             deserialized_save_file,
             data,
             // Components
+            Ability,
             AreaOfEffect,
+            Barks,
             BlocksTile,
+            Charges,
+            Charms,
             CombatStats,
+            Companion,
             Confusion,
             Consumable,
+            Container,
+            Cursed,
+            DetectItems,
+            DetectMonsters,
+            Encumbered,
+            Faction,
+            Gold,
+            HungerClock,
+            Identifies,
             InBackpack,
+            InContainer,
             InflictsDamage,
             Item,
+            KeepsDistance,
+            KnownSpell,
+            LeavesCorpse,
+            Locked,
+            LootTable,
+            MagicMapper,
+            Mana,
             Monster,
             Name,
+            Peaceful,
             Player,
             Position,
+            ProvidesFood,
             ProvidesHealing,
+            Purse,
             Ranged,
+            RangedWeapon,
+            Recharges,
+            RemovesCurse,
             Renderer,
             SerializationHelper,
+            Shatters,
+            Stackable,
             SufferDamage,
+            Summons,
+            TeachesSpell,
+            Teleports,
+            UsesItems,
+            Value,
+            Vendor,
             Viewshed,
+            Wanders,
             WantsToDropItem,
+            WantsToLearnSpell,
             WantsToMelee,
             WantsToPickupItem,
-            WantsToUseItem
+            WantsToShoot,
+            WantsToSummon,
+            WantsToThrowItem,
+            WantsToUseItem,
+            Weight
         );
     }
 
@@ -172,6 +259,13 @@ pub fn load_game(ecs: &mut World) {
             let mut world_map = ecs.write_resource::<Map>();
             *world_map = helper.map.clone();
             world_map.tile_contents = vec![Vec::new(); MAP_COUNT]; // Since we aren't serializing tile_content, we replace it with an empty set of vectors.
+            // Restore which items are identified, naming any the save doesn't know about.
+            let mut item_identification = ecs.write_resource::<ItemIdentification>();
+            *item_identification = helper.item_identification.clone();
+            item_identification.add_missing_names(
+                &ecs.fetch::<RawMaster>(),
+                &mut ecs.write_resource::<rltk::RandomNumberGenerator>(),
+            );
             entity_to_delete = Some(entity);
         }
         // Add (the loaded) player entity and position resources to ECS.
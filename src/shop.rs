@@ -0,0 +1,68 @@
+use specs::prelude::*;
+use super::inventory_system::can_carry;
+use super::{spawner, Cursed, GameLog, InBackpack, ItemIdentification, Name, Purse, Value, Weight};
+
+// Shops buy things back for this percentage of what they sell them for.
+const SELL_PERCENT: i32 = 50;
+
+/// What a shop charges for one of an item.
+pub fn buy_price(value: &Value) -> i32 {
+    value.gold
+}
+
+/// What a shop pays for one of an item. Never nothing, so even the cheapest things are worth selling.
+pub fn sell_price(value: &Value) -> i32 {
+    i32::max(1, value.gold * SELL_PERCENT / 100)
+}
+
+/// The player buys one of "item" from the shopkeeper's stock, if they can afford and carry it.
+pub fn buy_item(ecs: &mut World, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let item_name = ecs.fetch::<ItemIdentification>().display_name(&ecs.read_storage::<Name>().get(item).unwrap().name);
+    let price = match ecs.read_storage::<Value>().get(item) {
+        None => return,
+        Some(value) => buy_price(value),
+    };
+    let gold = ecs.read_storage::<Purse>().get(player_entity).map_or(0, |purse| purse.gold);
+    if gold < price {
+        ecs.write_resource::<GameLog>().entries.push(format!("You can't afford the {}.", item_name));
+        return;
+    }
+    // Bought things go straight into the backpack, so the player can only carry so much, just like when picking things up.
+    let pounds = ecs.read_storage::<Weight>().get(item).map_or(0.0, |weight| weight.pounds);
+    if !can_carry(ecs, player_entity, pounds) {
+        ecs.write_resource::<GameLog>().entries.push(format!("You can't carry the {} as well.", item_name));
+        return;
+    }
+
+    let bought_item = spawner::split_stack(ecs, item);
+    ecs.write_storage::<InBackpack>().remove(bought_item);
+    spawner::put_in_backpack(ecs, bought_item, player_entity);
+    if let Some(purse) = ecs.write_storage::<Purse>().get_mut(player_entity) {
+        purse.gold -= price;
+    }
+    ecs.write_resource::<GameLog>().entries.push(format!("You buy the {} for {} gold.", item_name, price));
+}
+
+/// The player sells one of "item" to the shopkeeper. Cursed items won't leave their hands, just like when dropping them.
+pub fn sell_item(ecs: &mut World, shopkeeper: Entity, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let item_name = ecs.fetch::<ItemIdentification>().display_name(&ecs.read_storage::<Name>().get(item).unwrap().name);
+    if let Some(cursed) = ecs.write_storage::<Cursed>().get_mut(item) {
+        cursed.discovered = true;
+        ecs.write_resource::<GameLog>().entries.push(format!("You can't let go of the {}. It's cursed!", item_name));
+        return;
+    }
+    let price = match ecs.read_storage::<Value>().get(item) {
+        None => return,
+        Some(value) => sell_price(value),
+    };
+
+    let sold_item = spawner::split_stack(ecs, item);
+    ecs.write_storage::<InBackpack>().remove(sold_item);
+    spawner::put_in_backpack(ecs, sold_item, shopkeeper);
+    if let Some(purse) = ecs.write_storage::<Purse>().get_mut(player_entity) {
+        purse.gold += price;
+    }
+    ecs.write_resource::<GameLog>().entries.push(format!("You sell the {} for {} gold.", item_name, price));
+}
@@ -0,0 +1,163 @@
+use rltk::RandomNumberGenerator;
+use super::{raws, spawner, Map, RandomTable, Rect, TileType};
+
+const NAME_WIDTH: usize = 24;
+
+// How many times each table is rolled when checking it against its expected distribution.
+const VERIFICATION_ROLLS: i32 = 100_000;
+
+// How many levels are generated at each depth when checking where things spawn.
+const PLACEMENT_LEVELS: i32 = 200;
+
+// The standard normal value that only 0.1% of samples exceed.
+const Z_SCORE_AT_0_1_PERCENT: f64 = 3.09;
+
+/// Prints how likely each spawn table entry is at every depth from 1 to "max_depth",
+/// along with each level's spawn budget and guaranteed spawns. Then rolls every spawn, shop and
+/// loot table many times, and checks that what comes up matches those chances. Finally generates
+/// levels at every depth and checks that their planned spawns all land on free floor tiles.
+/// Run with "cargo run -- --spawn-analysis 10".
+pub fn run_spawn_analysis(max_depth: i32) {
+    let raw_master = raws::load_raws();
+    let depths: Vec<i32> = (1..=max_depth).collect();
+
+    println!("Expected spawn distribution (% of spawn table rolls) for depths 1 to {}", max_depth);
+    print_row("Depth", &depths, |depth| depth.to_string());
+
+    for name in raw_master.spawn_table_names() {
+        print_row(name, &depths, |depth| {
+            let weights = raw_master.spawn_weights(depth);
+            let total_weight: i32 = weights.iter().map(|(_name, weight)| weight).sum();
+            match weights.iter().find(|(entry_name, _weight)| *entry_name == name) {
+                Some((_name, weight)) => format!("{:.1}", *weight as f32 * 100.0 / total_weight as f32),
+                None => "-".to_string(),
+            }
+        });
+    }
+
+    println!();
+    print_row("Spawn budget", &depths, |depth| raw_master.spawn_budget(depth).to_string());
+    for name in raw_master.guaranteed_spawn_names() {
+        print_row(&format!("Guaranteed {}", name), &depths, |depth| {
+            let count: i32 = raw_master
+                .guaranteed_spawns(depth)
+                .iter()
+                .filter(|(guaranteed_name, _count)| guaranteed_name == name)
+                .map(|(_name, count)| count)
+                .sum();
+            count.to_string()
+        });
+    }
+
+    println!();
+    println!("Checking each table against {} seeded rolls (chi-squared test, 0.1% significance)", VERIFICATION_ROLLS);
+    println!("{:<width$} | {:>10} | {:>4} | {:>10} | Result", "Table", "Chi-square", "DoF", "Critical", width = NAME_WIDTH);
+    let mut rng = RandomNumberGenerator::seeded(max_depth as u64);
+    for depth in depths.iter() {
+        verify_table(&format!("Spawns at depth {}", depth), &raw_master.spawn_table(*depth), &mut rng);
+    }
+    for depth in depths.iter() {
+        verify_table(&format!("Shop stock at depth {}", depth), &raw_master.shop_table(*depth), &mut rng);
+    }
+    for name in raw_master.loot_table_names() {
+        verify_table(&format!("Loot: {}", name), &raw_master.loot_table(name), &mut rng);
+    }
+
+    println!();
+    println!("Checking spawn placement on {} generated levels per depth", PLACEMENT_LEVELS);
+    println!("{:<width$} | {:>10} | {:>10} | Result", "Depth", "Avg spawns", "Max spawns", width = NAME_WIDTH);
+    for depth in depths.iter() {
+        verify_placement(*depth, &raw_master, &mut rng);
+    }
+}
+
+// Plans spawns for many generated levels, and makes sure every one is on an unused floor tile
+// outside the player's starting room and the shop.
+fn verify_placement(depth: i32, raw_master: &raws::RawMaster, rng: &mut RandomNumberGenerator) {
+    let mut total_spawns = 0;
+    let mut most_spawns = 0;
+    let mut misplaced_spawns = 0;
+    for _level in 0..PLACEMENT_LEVELS {
+        let map = Map::new_map_rooms_and_corridors(depth);
+        let shop_room = spawner::plan_shop_room(&map, raw_master, rng);
+        let spawns = spawner::plan_level_spawns(&map, shop_room, raw_master, rng);
+        total_spawns += spawns.len();
+        most_spawns = usize::max(most_spawns, spawns.len());
+
+        let in_room = |room: &Rect, x: i32, y: i32| x > room.x1 && x <= room.x2 && y > room.y1 && y <= room.y2;
+        for (i, (index, _name)) in spawns.iter().enumerate() {
+            let x = *index as i32 % map.width;
+            let y = *index as i32 / map.width;
+            let in_starting_room = in_room(&map.rooms[0], x, y);
+            let in_shop = shop_room.is_some_and(|shop_room| in_room(&map.rooms[shop_room], x, y));
+            let shared = spawns[..i].iter().any(|(other_index, _name)| other_index == index);
+            if map.tiles[*index] != TileType::Floor || in_starting_room || in_shop || shared {
+                misplaced_spawns += 1;
+            }
+        }
+    }
+
+    let result = if misplaced_spawns > 0 {
+        format!("FAIL ({} spawns on walls, stairs, taken tiles, or in the starting room or shop)", misplaced_spawns)
+    } else {
+        "OK".to_string()
+    };
+    println!(
+        "{:<width$} | {:>10.1} | {:>10} | {}",
+        depth, total_spawns as f32 / PLACEMENT_LEVELS as f32, most_spawns, result,
+        width = NAME_WIDTH,
+    );
+}
+
+// Rolls the table and compares how often each name came up with how often it should have.
+fn verify_table(label: &str, table: &RandomTable, rng: &mut RandomNumberGenerator) {
+    let probabilities = table.probabilities();
+    let mut observed: Vec<i32> = vec![0; probabilities.len()];
+    let mut unexpected_rolls = 0;
+    for _roll in 0..VERIFICATION_ROLLS {
+        let name = table.roll(rng);
+        match probabilities.iter().position(|(expected_name, _chance)| *expected_name == name) {
+            Some(index) => observed[index] += 1,
+            None => unexpected_rolls += 1,
+        }
+    }
+
+    let chi_square: f64 = probabilities
+        .iter()
+        .zip(observed.iter())
+        .map(|((_name, chance), observed)| {
+            let expected = *chance as f64 * VERIFICATION_ROLLS as f64;
+            (*observed as f64 - expected).powi(2) / expected
+        })
+        .sum();
+    let degrees_of_freedom = probabilities.len().saturating_sub(1);
+    let critical_value = chi_square_critical_value(degrees_of_freedom);
+    let result = if unexpected_rolls > 0 {
+        format!("FAIL ({} rolls of names that aren't in the table)", unexpected_rolls)
+    } else if chi_square > critical_value {
+        "FAIL".to_string()
+    } else {
+        "OK".to_string()
+    };
+    println!(
+        "{:<width$} | {:>10.2} | {:>4} | {:>10.2} | {}",
+        label, chi_square, degrees_of_freedom, critical_value, result,
+        width = NAME_WIDTH,
+    );
+}
+
+// The Wilson-Hilferty approximation of the chi-squared value only exceeded 0.1% of the time.
+pub fn chi_square_critical_value(degrees_of_freedom: usize) -> f64 {
+    if degrees_of_freedom == 0 { return 0.0; }
+    let k = degrees_of_freedom as f64;
+    let spread = 2.0 / (9.0 * k);
+    k * (1.0 - spread + Z_SCORE_AT_0_1_PERCENT * spread.sqrt()).powi(3)
+}
+
+fn print_row<F: Fn(i32) -> String>(label: &str, depths: &[i32], cell: F) {
+    print!("{:<width$}", label, width = NAME_WIDTH);
+    for depth in depths.iter() {
+        print!(" | {:>5}", cell(*depth));
+    }
+    println!();
+}
@@ -1,183 +1,305 @@
-use std::collections::HashMap;
-
 use rltk::{RGB, RandomNumberGenerator};
 use specs::prelude::*;
 use specs::saveload::{MarkedBuilder, SimpleMarker};
 use super::{
-    AreaOfEffect,
-    BlocksTile,
+    hunger_system::WELL_FED_TURNS,
+    inventory_system,
+    raws::{spawn_bare_item, spawn_named_entity, spawn_spell, RawMaster},
+    Charges,
     CombatStats,
-    Confusion,
+    Companion,
+    CompanionOrder,
     Consumable,
-    InflictsDamage,
+    Container,
+    Cursed,
+    Faction,
+    FactionTable,
+    GameLog,
+    Gold,
+    HungerClock,
+    HungerState,
+    InBackpack,
+    InContainer,
     Item,
-    MAP_WIDTH,
-    Monster,
+    Mana,
+    Map,
     Name,
     Player,
     Position,
-    ProvidesHealing,
+    ProvidesFood,
+    Purse,
     SerializeMe,
-    Ranged,
+    Reaction,
     Rect,
     Renderer,
-    RandomTable,
+    Stackable,
+    TileType,
+    Vendor,
     Viewshed,
+    Wanders,
+    WantsToLearnSpell,
+    WantsToSummon,
+    Weight,
 };
 
-const MAX_MONSTERS: i32 = 4;
+// Rooms can hold one more spawn for every this many floor tiles...
+const FLOOR_TILES_PER_SPAWN: i32 = 20;
+// ...and one more for every this many levels down.
+const DEPTHS_PER_EXTRA_SPAWN: i32 = 4;
 
-pub fn fill_room(ecs: &mut World, room: &Rect, map_depth: i32) {
-    let spawn_table = room_table(map_depth);
-    let mut spawn_points: HashMap<usize, String> = HashMap::new();
+// How many pounds a corpse weighs, whatever it was.
+const CORPSE_WEIGHT: f32 = 5.0;
 
-    // Scope to keep the borrow checker happy
-    {
+/// Fills a freshly generated level with everything "plan_level_spawns" decides on,
+/// and opens a shop if "plan_shop_room" picked a room for one.
+pub fn populate_level(ecs: &mut World, map: &Map) {
+    let (shop_room, spawns) = {
+        let raw_master = ecs.fetch::<RawMaster>();
         let mut random_number_generator = ecs.write_resource::<RandomNumberGenerator>();
-        let total_spawns = random_number_generator.roll_dice(1, MAX_MONSTERS + 3) - 3;
+        let shop_room = plan_shop_room(map, &raw_master, &mut random_number_generator);
+        (shop_room, plan_level_spawns(map, shop_room, &raw_master, &mut random_number_generator))
+    };
+
+    for (index, name) in spawns.iter() {
+        let x = *index as i32 % map.width;
+        let y = *index as i32 / map.width;
+        spawn_named(ecs, name, x, y);
+    }
+
+    if let Some(shop_room) = shop_room {
+        open_shop(ecs, map.depth, &map.rooms[shop_room]);
+    }
+}
+
+/// Fills the town with its shops and townsfolk. The first few buildings are shops, stocked as they would be
+/// on the first level of the dungeon, and the townsfolk go anywhere else but the square the player starts in.
+pub fn populate_town(ecs: &mut World, map: &Map) {
+    let (shop_count, townsfolk) = {
+        let raw_master = ecs.fetch::<RawMaster>();
+        match raw_master.town() {
+            None => return,
+            Some(town) => (town.shop_count as usize, town.townsfolk.clone()),
+        }
+    };
+
+    let shops: Vec<Rect> = map.rooms.iter().skip(1).take(shop_count).copied().collect();
+    let is_in_shop = |x: i32, y: i32| shops.iter().any(|shop| x > shop.x1 && x <= shop.x2 && y > shop.y1 && y <= shop.y2);
+    let mut free_tiles: Vec<usize> = Vec::new();
+    for y in 1..map.height - 1 {
+        for x in 1..map.width - 1 {
+            let index = map.xy_idx(x, y);
+            let is_in_square = x > map.rooms[0].x1 && x <= map.rooms[0].x2 && y > map.rooms[0].y1 && y <= map.rooms[0].y2;
+            if map.tiles[index] == TileType::Floor && !is_in_square && !is_in_shop(x, y) {
+                free_tiles.push(index);
+            }
+        }
+    }
+
+    for shop in shops.iter() {
+        open_shop(ecs, 1, shop);
+    }
+    for townsfolk in townsfolk.iter() {
+        for _i in 0..townsfolk.count {
+            let index = {
+                let mut random_number_generator = ecs.write_resource::<RandomNumberGenerator>();
+                take_random_tile(&mut free_tiles, &mut random_number_generator)
+            };
+            if let Some(index) = index {
+                spawn_named(ecs, &townsfolk.name, index as i32 % map.width, index as i32 / map.width);
+            }
+        }
+    }
+}
+
+/// Decides whether a freshly generated level gets a shop, and if so which room it's in.
+/// Never the player's starting room or the room with the stairs.
+pub fn plan_shop_room(map: &Map, raw_master: &RawMaster, random_number_generator: &mut RandomNumberGenerator) -> Option<usize> {
+    let shops = raw_master.shops()?;
+    if map.rooms.len() < 3 { return None; }
+    if random_number_generator.range(0, 100) >= shops.chance { return None; }
+    Some(random_number_generator.range(1, map.rooms.len() as i32 - 1) as usize)
+}
+
+/// Decides what spawns where on a freshly generated level, as (map index, name) pairs.
+/// Guaranteed spawns are placed first, then each room gets a share of the spawn table that grows
+/// with its floor area and the depth, until the level's spawn budget runs out.
+/// Things only spawn on floor tiles inside rooms (never on walls or stairs), at most one per tile,
+/// and the player's starting room and the shop (if there is one) are left empty.
+/// Doesn't touch the World, so it can be called on its own to check the results.
+pub fn plan_level_spawns(
+    map: &Map,
+    shop_room: Option<usize>,
+    raw_master: &RawMaster,
+    random_number_generator: &mut RandomNumberGenerator,
+) -> Vec<(usize, String)> {
+    let mut spawns: Vec<(usize, String)> = Vec::new();
+    let spawn_table = raw_master.spawn_table(map.depth);
+    let mut budget = raw_master.spawn_budget(map.depth);
+
+    // The free tiles of every room but the first and the shop, visited in a random order so
+    // the budget doesn't always run out in the same part of the map.
+    let mut rooms: Vec<Vec<usize>> = map.rooms
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(index, _room)| Some(*index) != shop_room)
+        .map(|(_index, room)| spawnable_tiles(map, room))
+        .collect();
+    for i in (1..rooms.len()).rev() {
+        let j = random_number_generator.range(0, i as i32 + 1) as usize;
+        rooms.swap(i, j);
+    }
 
+    for (name, count) in raw_master.guaranteed_spawns(map.depth) {
+        for _i in 0..count {
+            let rooms_with_space: Vec<usize> = (0..rooms.len()).filter(|room| !rooms[*room].is_empty()).collect();
+            if rooms_with_space.is_empty() { break; }
+            let room = rooms_with_space[random_number_generator.range(0, rooms_with_space.len() as i32) as usize];
+            if let Some(index) = take_random_tile(&mut rooms[room], random_number_generator) {
+                spawns.push((index, name.clone()));
+            }
+        }
+    }
+
+    for room_tiles in rooms.iter_mut() {
+        let room_budget = room_tiles.len() as i32 / FLOOR_TILES_PER_SPAWN + map.depth / DEPTHS_PER_EXTRA_SPAWN;
+        let total_spawns = random_number_generator.range(0, room_budget + 1);
         for _i in 0..total_spawns {
-            let mut added = false;
-            let mut tries = 0;
-            while !added && tries < 20 {
-                let x = (room.x1 + random_number_generator.roll_dice(1, i32::abs(room.x2 - room.x1))) as usize;
-                let y = (room.y1 + random_number_generator.roll_dice(1, i32::abs(room.y2 - room.y1))) as usize;
-                let index = (y * MAP_WIDTH) + x;
-                if !spawn_points.contains_key(&index) {
-                    spawn_points.insert(index, spawn_table.roll(&mut random_number_generator));
-                    added = true;
-                } else {
-                    tries += 1;
-                }
+            if budget <= 0 { break; }
+            let name = spawn_table.roll(random_number_generator);
+            // Rolling nothing shouldn't use up a tile or any of the budget.
+            if name == "None" { continue; }
+            let cost = raw_master.spawn_cost(&name);
+            if cost > budget { continue; }
+            if let Some(index) = take_random_tile(room_tiles, random_number_generator) {
+                spawns.push((index, name));
+                budget -= cost;
             }
         }
     }
 
-    // Actually spawn the monsters
-    for spawn in spawn_points.iter() {
-        let x = (*spawn.0 % MAP_WIDTH) as i32;
-        let y = (*spawn.0 / MAP_WIDTH) as i32;
-
-        match spawn.1.as_ref() {
-            "Goblin" => spawn_goblin(ecs, x, y),
-            "Orc" => spawn_orc(ecs, x, y),
-            "Health Potion" => spawn_health_potion(ecs, x, y),
-            "Fireball Scroll" => spawn_fireball_scroll(ecs, x, y),
-            "Confusion Scroll" => spawn_confusion_scroll(ecs, x, y),
-            "Magic Missile Scroll" => spawn_magic_missile_scroll(ecs, x, y),
-            _ => {}
+    spawns
+}
+
+// The floor tiles inside a room.
+fn spawnable_tiles(map: &Map, room: &Rect) -> Vec<usize> {
+    let mut tiles: Vec<usize> = Vec::new();
+    for y in room.y1 + 1..=room.y2 {
+        for x in room.x1 + 1..=room.x2 {
+            if x < 0 || x >= map.width || y < 0 || y >= map.height { continue; }
+            let index = map.xy_idx(x, y);
+            if map.tiles[index] == TileType::Floor {
+                tiles.push(index);
+            }
         }
     }
+    tiles
 }
 
-fn room_table(map_depth: i32) -> RandomTable {
-    RandomTable::new()
-        .add("Goblin", 10)
-        .add("Orc", 1 + map_depth)
-        .add("Health Potion", 7)
-        .add("Fireball Scroll", 2 + map_depth)
-        .add("Confusion Scroll", 2 + map_depth)
-        .add("Magic Missile Scroll", 4)
+// Removes a random tile from the list, so nothing else spawns there.
+fn take_random_tile(tiles: &mut Vec<usize>, random_number_generator: &mut RandomNumberGenerator) -> Option<usize> {
+    if tiles.is_empty() { return None; }
+    let tile = random_number_generator.range(0, tiles.len() as i32) as usize;
+    Some(tiles.swap_remove(tile))
 }
 
-fn spawn_confusion_scroll(ecs: &mut World, x: i32, y: i32) {
-    ecs
-        .create_entity()
-        // Components
-        .with(Confusion {
-            turns: 4
-        })
-        .with(Consumable {})
-        .with(Item {})
-        .with(Name { name: "Confusion Scroll".to_string() })
-        .with(Position { x, y })
-        .with(Ranged {
-            range: 6,
-        })
-        .with(Renderer {
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::PINK),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        // Markers
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+// Spawns whatever a RandomTable rolled. "None" (or anything unknown) spawns nothing.
+fn spawn_named(ecs: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    let entity = match name {
+        "None" => None,
+        "Gold" => Some(spawn_gold(ecs, x, y)),
+        _ => spawn_named_entity(ecs, name, x, y),
+    }?;
+    fill_container(ecs, entity, name, x, y);
+    Some(entity)
 }
 
-fn spawn_fireball_scroll(ecs: &mut World, x: i32, y: i32) {
-    ecs
-        .create_entity()
-        // Components
-        .with(AreaOfEffect {
-            radius: 3
-        })
-        .with(Consumable {})
-        .with(InflictsDamage {
-            damage: 20
-        })
-        .with(Item {})
-        .with(Name { name: "Fireball Scroll".to_string() })
-        .with(Position { x, y })
-        .with(Ranged {
-            range: 6,
-        })
-        .with(Renderer {
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::ORANGE),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        // Markers
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+// Fills a container prop from its loot table in the raws. Anything else is left alone.
+fn fill_container(ecs: &mut World, container: Entity, name: &str, x: i32, y: i32) {
+    let contents: Vec<String> = {
+        let raw_master = ecs.fetch::<RawMaster>();
+        let mut random_number_generator = ecs.write_resource::<RandomNumberGenerator>();
+        let (table_name, rolls) = match raw_master.container_loot(name) {
+            None => return,
+            Some(container_loot) => container_loot,
+        };
+        let loot_table = raw_master.loot_table(table_name);
+        (0..rolls).map(|_i| loot_table.roll(&mut random_number_generator)).collect()
+    };
+
+    for name in contents {
+        if let Some(entity) = spawn_named(ecs, &name, x, y) {
+            // Only items fit inside. Anything else the table rolls is left standing next to it.
+            if ecs.read_storage::<Item>().contains(entity) {
+                ecs.write_storage::<Position>().remove(entity);
+                put_in_container(ecs, entity, container);
+            }
+        }
+    }
 }
 
-fn spawn_magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
-    ecs
-        // Components
-        .create_entity()
-        .with(Consumable {})
-        .with(InflictsDamage {
-            damage: 8
-        })
-        .with(Item {})
-        .with(Name { name: "Magic Missile Scroll".to_string() })
-        .with(Position { x, y })
-        .with(Ranged {
-            range: 6,
-        })
-        .with(Renderer {
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::CYAN),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        // Markers
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+// Puts a shopkeeper in the middle of the room, with a backpack full of stock rolled from the shop table.
+fn open_shop(ecs: &mut World, map_depth: i32, room: &Rect) {
+    let (shopkeeper_name, stock) = {
+        let raw_master = ecs.fetch::<RawMaster>();
+        let mut random_number_generator = ecs.write_resource::<RandomNumberGenerator>();
+        let shops = match raw_master.shops() {
+            None => return,
+            Some(shops) => shops,
+        };
+        // Each item is stocked once, so a shop doesn't fill up with the most common things.
+        let stock = raw_master.shop_table(map_depth).sample_without_replacement(shops.stock_size as usize, &mut random_number_generator);
+        (shops.shopkeeper.clone(), stock)
+    };
+
+    let (x, y) = room.center();
+    let shopkeeper = match spawn_named_entity(ecs, &shopkeeper_name, x, y) {
+        None => return,
+        Some(shopkeeper) => shopkeeper,
+    };
+    ecs.write_storage::<Vendor>()
+        .insert(shopkeeper, Vendor {})
+        .expect("Unable to insert Vendor component.");
+
+    for name in stock {
+        if let Some(item) = spawn_named_entity(ecs, &name, x, y) {
+            ecs.write_storage::<Position>().remove(item);
+            // Shopkeepers know better than to sell cursed goods.
+            ecs.write_storage::<Cursed>().remove(item);
+            put_in_backpack(ecs, item, shopkeeper);
+        }
+    }
 }
 
-fn spawn_health_potion(ecs: &mut World, x: i32, y: i32) {
-    ecs
-        .create_entity()
-        // Components
-        .with(Consumable {})
-        .with(Item {})
-        .with(Name { name: "Health Potion".to_string() })
-        .with(Position { x, y })
-        .with(ProvidesHealing {
-            heal_amount: 8
-        })
-        .with(Renderer {
-            glyph: rltk::to_cp437('¡'),
-            fg: RGB::named(rltk::GREEN),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        // Markers
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+/// Rolls a loot table and drops the result at the given position, returning it if anything dropped.
+pub fn drop_loot(ecs: &mut World, table_name: &str, x: i32, y: i32) -> Option<Entity> {
+    let drop = {
+        let mut random_number_generator = ecs.write_resource::<RandomNumberGenerator>();
+        let raw_master = ecs.fetch::<RawMaster>();
+        raw_master.loot_table(table_name).roll(&mut random_number_generator)
+    };
+    spawn_named(ecs, &drop, x, y)
+}
+
+pub fn faction_table() -> FactionTable {
+    FactionTable::new()
+        // The Player's allies
+        .add_default("Player", Reaction::Attack)
+        .add("Player", "Wildlife", Reaction::Ignore)
+        .add("Player", "Shopkeepers", Reaction::Ignore)
+        .add("Player", "Townsfolk", Reaction::Ignore)
+        // Orcs and Goblins hate everyone, including each other, but leave shopkeepers alone.
+        .add_default("Orcs", Reaction::Attack)
+        .add("Orcs", "Wildlife", Reaction::Ignore)
+        .add("Orcs", "Shopkeepers", Reaction::Ignore)
+        .add_default("Goblins", Reaction::Attack)
+        .add("Goblins", "Wildlife", Reaction::Ignore)
+        .add("Goblins", "Shopkeepers", Reaction::Ignore)
+        // Wildlife keeps to itself.
+        .add_default("Wildlife", Reaction::Ignore)
+        // Shopkeepers would rather trade than fight, and the townsfolk just want a quiet life.
+        .add_default("Shopkeepers", Reaction::Ignore)
+        .add_default("Townsfolk", Reaction::Ignore)
 }
 
 pub fn spawn_player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
@@ -190,11 +312,22 @@ pub fn spawn_player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
             defense: 2,
             power: 5,
         })
+        .with(Faction { name: "Player".to_string() })
+        .with(HungerClock {
+            state: HungerState::WellFed,
+            duration: WELL_FED_TURNS,
+        })
+        .with(Mana {
+            current: 10,
+            max: 10,
+            regeneration_turns: 0,
+        })
         .with(Name {
             name: "Player".to_string()
         })
         .with(Player {})
         .with(Position { x: player_x, y: player_y })
+        .with(Purse { gold: 0 })
         .with(Renderer {
             glyph: rltk::to_cp437('@'),
             fg: RGB::named(rltk::YELLOW),
@@ -211,50 +344,305 @@ pub fn spawn_player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
         .build()
 }
 
-fn spawn_goblin(ecs: &mut World, x: i32, y: i32) {
-    spawn_monster(
-        ecs,
-        x, y,
-        rltk::to_cp437('g'),
-        "Goblin".to_string());
-}
-
-fn spawn_orc(ecs: &mut World, x: i32, y: i32) {
-    spawn_monster(
-        ecs,
-        x, y,
-        rltk::to_cp437('o'),
-        "Orc".to_string());
+fn spawn_gold(ecs: &mut World, x: i32, y: i32) -> Entity {
+    // Deeper monsters carry more gold. The town counts as the first level.
+    let amount = {
+        let map_depth = ecs.fetch::<Map>().depth;
+        let mut random_number_generator = ecs.write_resource::<RandomNumberGenerator>();
+        random_number_generator.roll_dice(2, 6) + i32::max(0, map_depth - 1) * 2
+    };
+    ecs
+        .create_entity()
+        // Components
+        .with(Gold { amount })
+        .with(Item {})
+        .with(Name { name: format!("{} Gold", amount) })
+        .with(Position { x, y })
+        .with(Renderer {
+            glyph: rltk::to_cp437('$'),
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        // Markers
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
 }
 
-fn spawn_monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: rltk::FontCharType, name: S) {
+/// Leaves the remains of a dead creature on the map. They can be picked up and eaten, if the player is desperate.
+/// Whatever the creature carried can be searched for in them, since a corpse is a Container.
+pub fn spawn_corpse(ecs: &mut World, name: &str, x: i32, y: i32) -> Entity {
     ecs
-        // Components
         .create_entity()
-        .with(BlocksTile {})
-        .with(CombatStats {
-            max_hp: 16,
-            hp: 16,
-            defense: 1,
-            power: 4,
-        })
-        .with(Monster {})
-        .with(Name {
-            name: format!("{}", name.to_string())
-        })
+        // Components
+        .with(Consumable {})
+        .with(Container {})
+        .with(Item {})
+        .with(Name { name: format!("{} Corpse", name) })
         .with(Position { x, y })
+        .with(ProvidesFood {})
         .with(Renderer {
-            glyph,
-            fg: RGB::named(rltk::RED),
+            glyph: rltk::to_cp437('%'),
+            fg: RGB::named(rltk::DARK_RED),
             bg: RGB::named(rltk::BLACK),
-            render_order: 1,
-        })
-        .with(Viewshed {
-            visible_tiles: Vec::new(),
-            range: 8,
-            dirty: true,
+            // Drawn underneath items, so loot isn't hidden by the body it fell from.
+            render_order: 3,
         })
+        .with(Weight { pounds: CORPSE_WEIGHT })
         // Markers
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
-}
\ No newline at end of file
+        .build()
+}
+
+/// Spawns the companions asked for by WantsToSummon (e.g. from a Summoning Scroll) on free tiles near the summoner.
+pub fn summon_companions(ecs: &mut World) {
+    let mut summons: Vec<(String, rltk::Point)> = Vec::new();
+    {
+        let mut wants_to_summon_components = ecs.write_storage::<WantsToSummon>();
+        for wants_to_summon in wants_to_summon_components.join() {
+            summons.push((wants_to_summon.name.clone(), wants_to_summon.position));
+        }
+        wants_to_summon_components.clear();
+    }
+
+    for (name, position) in summons {
+        let free_tile = {
+            let mut map = ecs.write_resource::<Map>();
+            let free_tile = find_free_tile_near(&map, position);
+            // Block the tile straight away, so two summons don't land on top of each other.
+            if let Some((x, y)) = free_tile {
+                let index = map.xy_idx(x, y);
+                map.blocked_tiles[index] = true;
+            }
+            free_tile
+        };
+        let (x, y) = match free_tile {
+            None => continue,
+            Some(free_tile) => free_tile,
+        };
+        if let Some(companion) = spawn_named(ecs, &name, x, y) {
+            make_companion(ecs, companion);
+        }
+    }
+}
+
+/// Teaches the player the spells every new game starts with. Needs the raws to be loaded.
+pub fn learn_starting_spells(ecs: &mut World, player_entity: Entity) {
+    let starting_spell_names = ecs.fetch::<RawMaster>().starting_spell_names();
+    for name in starting_spell_names {
+        spawn_spell(ecs, &name, player_entity);
+    }
+}
+
+/// Teaches the spells asked for by WantsToLearnSpell (e.g. from a spell scroll).
+pub fn learn_spells(ecs: &mut World) {
+    let mut lessons: Vec<(Entity, String)> = Vec::new();
+    {
+        let entities = ecs.entities();
+        let mut wants_to_learn_spells = ecs.write_storage::<WantsToLearnSpell>();
+        for (entity, wants_to_learn_spell) in (&entities, &wants_to_learn_spells).join() {
+            lessons.push((entity, wants_to_learn_spell.name.clone()));
+        }
+        wants_to_learn_spells.clear();
+    }
+
+    for (entity, name) in lessons {
+        if spawn_spell(ecs, &name, entity).is_some() && ecs.read_storage::<Player>().contains(entity) {
+            ecs.write_resource::<GameLog>().entries.push(format!("You learn the {} spell!", name));
+        }
+    }
+}
+
+/// Takes one item off a stack, as a new entity in the same backpack, and returns it.
+/// Anything else, including cursed stacks (which can't be parted with anyway), is returned as it is.
+pub fn split_stack(ecs: &mut World, item: Entity) -> Entity {
+    let owner = {
+        let stackables = ecs.read_storage::<Stackable>();
+        let is_stack = stackables.get(item).is_some_and(|stackable| stackable.quantity > 1);
+        if !is_stack || ecs.read_storage::<Cursed>().contains(item) { return item; }
+        match ecs.read_storage::<InBackpack>().get(item) {
+            None => return item,
+            Some(in_backpack) => in_backpack.owner,
+        }
+    };
+
+    // The single item comes from the raws like any other, and only takes what was rolled for the stack.
+    let name = ecs.read_storage::<Name>().get(item).unwrap().name.clone();
+    let single_item = match spawn_bare_item(ecs, &name) {
+        None => return item,
+        Some(single_item) => single_item,
+    };
+    copy_component::<Charges>(ecs, item, single_item);
+    copy_component::<Cursed>(ecs, item, single_item);
+    ecs.write_storage::<Stackable>()
+        .insert(single_item, Stackable { quantity: 1 })
+        .expect("Unable to insert Stackable component.");
+    ecs.write_storage::<InBackpack>()
+        .insert(single_item, InBackpack { owner })
+        .expect("Unable to insert InBackpack component.");
+    ecs.write_storage::<Stackable>().get_mut(item).unwrap().quantity -= 1;
+    single_item
+}
+
+// Gives "to" a copy of "from"'s component of this type, if it has one.
+fn copy_component<T: Component + Clone>(ecs: &World, from: Entity, to: Entity) {
+    let mut storage = ecs.write_storage::<T>();
+    if let Some(component) = storage.get(from).cloned() {
+        storage.insert(to, component).expect("Unable to insert copied component.");
+    }
+}
+
+/// Puts an item that isn't on the map into "owner"'s backpack, joining a matching stack there if there is one.
+pub fn put_in_backpack(ecs: &mut World, item: Entity, owner: Entity) {
+    match matching_stack::<InBackpack>(ecs, item, |in_backpack| in_backpack.owner == owner) {
+        Some(existing_stack) => merge_stacks(ecs, item, existing_stack),
+        None => {
+            ecs.write_storage::<InBackpack>()
+                .insert(item, InBackpack { owner })
+                .expect("Unable to insert InBackpack component.");
+        }
+    }
+}
+
+/// Puts an item that isn't on the map or in a backpack into "container", joining a matching stack there if there is one.
+pub fn put_in_container(ecs: &mut World, item: Entity, container: Entity) {
+    match matching_stack::<InContainer>(ecs, item, |in_container| in_container.owner == container) {
+        Some(existing_stack) => merge_stacks(ecs, item, existing_stack),
+        None => {
+            ecs.write_storage::<InContainer>()
+                .insert(item, InContainer { owner: container })
+                .expect("Unable to insert InContainer component.");
+        }
+    }
+}
+
+// Finds a stack that "item" can join among the items whose "Holder" component passes "is_held".
+fn matching_stack<Holder: Component>(ecs: &World, item: Entity, is_held: impl Fn(&Holder) -> bool) -> Option<Entity> {
+    inventory_system::matching_stack(
+        &ecs.entities(),
+        &ecs.read_storage::<Cursed>(),
+        &ecs.read_storage::<Holder>(),
+        &ecs.read_storage::<Name>(),
+        &ecs.read_storage::<Stackable>(),
+        item,
+        is_held,
+    )
+}
+
+// Adds "item"'s quantity to "existing_stack", then deletes it.
+fn merge_stacks(ecs: &mut World, item: Entity, existing_stack: Entity) {
+    let mut stackables = ecs.write_storage::<Stackable>();
+    let quantity = stackables.get(item).unwrap().quantity;
+    stackables.get_mut(existing_stack).unwrap().quantity += quantity;
+    ecs.entities().delete(item).expect("Delete Entity Failed.");
+}
+
+/// Turns a monster into one of the player's companions.
+pub fn make_companion(ecs: &mut World, entity: Entity) {
+    recruit_companion(
+        &mut ecs.write_storage::<Faction>(),
+        &mut ecs.write_storage::<Companion>(),
+        &mut ecs.write_storage::<Wanders>(),
+        entity,
+    );
+}
+
+/// Like "make_companion(...)", for Systems that already hold the storages it changes.
+pub fn recruit_companion(
+    factions: &mut WriteStorage<Faction>,
+    companions: &mut WriteStorage<Companion>,
+    wanders_components: &mut WriteStorage<Wanders>,
+    entity: Entity,
+) {
+    factions.insert(entity, Faction { name: "Player".to_string() })
+        .expect("Unable to insert Faction component.");
+    companions.insert(entity, Companion { order: CompanionOrder::Follow })
+        .expect("Unable to insert Companion component.");
+    wanders_components.remove(entity);
+}
+
+/// The closest unblocked floor tile to "position", searching outwards a few tiles.
+/// Checks the terrain as well as blocked tiles, since a freshly built map hasn't been indexed yet.
+pub fn find_free_tile_near(map: &Map, position: rltk::Point) -> Option<(i32, i32)> {
+    for radius in 1..=3 {
+        for y in position.y - radius..=position.y + radius {
+            for x in position.x - radius..=position.x + radius {
+                if x == position.x && y == position.y { continue; }
+                if !map.is_terrain_walkable(x, y) || !map.is_exit_valid(x, y) { continue; }
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use rltk::RandomNumberGenerator;
+    use crate::raws::RawMaster;
+    use crate::{Map, TileType};
+    use super::plan_level_spawns;
+
+    const RAWS: &str = r##"{
+        "items": [
+            { "name": "Health Potion", "renderer": { "glyph": "!", "fg": "#FF00FF", "bg": "#000000", "order": 2 } },
+            { "name": "Food Ration", "renderer": { "glyph": "%", "fg": "#00FF00", "bg": "#000000", "order": 2 } }
+        ],
+        "mobs": [
+            {
+                "name": "Orc",
+                "renderer": { "glyph": "o", "fg": "#FF0000", "bg": "#000000", "order": 1 },
+                "faction": "Orcs",
+                "stats": { "max_hp": 16, "defense": 1, "power": 4 },
+                "vision_range": 8
+            }
+        ],
+        "props": [],
+        "spawn_table": [
+            { "name": "None", "weight": 10 },
+            { "name": "Health Potion", "weight": 3 },
+            { "name": "Orc", "weight": 3, "cost": 3 }
+        ],
+        "guaranteed_spawns": [
+            { "name": "Food Ration", "count": 2 }
+        ],
+        "spawn_budget": { "base": 6, "per_depth": 1 },
+        "loot_tables": []
+    }"##;
+
+    #[test]
+    fn planned_spawns_keep_to_the_rules() {
+        let raw_master = RawMaster::from_json(RAWS).expect("Unable to parse the test raws.");
+        let mut rng = RandomNumberGenerator::seeded(42);
+        for depth in 1..=10 {
+            for _level in 0..20 {
+                let map = Map::new_map_rooms_and_corridors(depth);
+                let shop_room = map.rooms.len() / 2;
+                let spawns = plan_level_spawns(&map, Some(shop_room), &raw_master, &mut rng);
+
+                let mut used_tiles: HashSet<usize> = HashSet::new();
+                for (index, name) in spawns.iter() {
+                    assert!(used_tiles.insert(*index), "two spawns on tile {}", index);
+                    assert!(map.tiles[*index] == TileType::Floor, "{} spawned off the floor", name);
+                    assert_ne!(name, "None");
+                    let (x, y) = (*index as i32 % map.width, *index as i32 / map.width);
+                    for room in [0, shop_room] {
+                        let room = &map.rooms[room];
+                        let is_inside = x >= room.x1 && x <= room.x2 && y >= room.y1 && y <= room.y2;
+                        assert!(!is_inside, "{} spawned in the starting room or the shop", name);
+                    }
+                }
+
+                let guaranteed = spawns.iter().filter(|(_index, name)| name == "Food Ration").count();
+                assert_eq!(guaranteed, 2);
+                let spent: i32 = spawns
+                    .iter()
+                    .filter(|(_index, name)| name != "Food Ration")
+                    .map(|(_index, name)| raw_master.spawn_cost(name))
+                    .sum();
+                assert!(spent <= raw_master.spawn_budget(depth), "spent {} of {}", spent, raw_master.spawn_budget(depth));
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use specs::prelude::*;
-use super::{Viewshed, Position, Map};
+use super::{DetectItems, DetectMonsters, GameLog, Viewshed, Position, Map, RunState};
 use rltk::{field_of_view, Point};
 use crate::Player;
 
@@ -39,4 +39,36 @@ impl<'a> System<'a> for VisibilitySystem {
             }
         }
     }
-}
\ No newline at end of file
+}
+/// Counts down the turns the player has left to sense items and monsters outside their view.
+pub struct DetectionSystem {}
+
+impl<'a> System<'a> for DetectionSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, DetectItems>,
+        WriteStorage<'a, DetectMonsters>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (player_entity, run_state, mut game_log, mut detect_items_components, mut detect_monsters_components) = data;
+        if *run_state != RunState::PlayerTurn { return; }
+
+        if let Some(detect_items) = detect_items_components.get_mut(*player_entity) {
+            detect_items.turns -= 1;
+            if detect_items.turns <= 0 {
+                detect_items_components.remove(*player_entity);
+                game_log.entries.push("You no longer sense the items around you.".to_string());
+            }
+        }
+        if let Some(detect_monsters) = detect_monsters_components.get_mut(*player_entity) {
+            detect_monsters.turns -= 1;
+            if detect_monsters.turns <= 0 {
+                detect_monsters_components.remove(*player_entity);
+                game_log.entries.push("You no longer sense the monsters around you.".to_string());
+            }
+        }
+    }
+}